@@ -0,0 +1,864 @@
+use inference_gateway_sdk::{
+    ChatCompletionMessageToolCall, CreateChatCompletionResponse, GatewayError, Message,
+    MessageRole, Provider, Tool,
+};
+use log::{debug, info, warn};
+use reqwest::{header::HeaderMap, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, panic,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::config::{Config, RetryConfig};
+use crate::conversation::Conversation;
+use crate::errors::CoderError;
+use crate::scm::ScmProvider;
+use crate::tools::{self, StatusResponse};
+use crate::utils;
+use crate::workspace::Workspace;
+use crate::worktree::Worktree;
+
+/// Why an agent run stopped
+#[derive(Debug, PartialEq)]
+pub enum AgentStatus {
+    /// The model called the `done` tool
+    Completed,
+    /// The gateway returned no choices
+    NoResponse,
+    /// The assistant message was empty after stripping the thinking block
+    EmptyResponse,
+    /// One of the configured run limits was reached
+    BudgetExhausted(Budget),
+}
+
+/// Limits configured in `AgentConfig`
+#[derive(Debug, PartialEq)]
+pub enum Budget {
+    Iterations(usize),
+    WallTime(Duration),
+    ToolCalls(usize),
+}
+
+impl fmt::Display for Budget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Budget::Iterations(max) => write!(f, "reached the limit of {} iterations", max),
+            Budget::WallTime(max) => write!(f, "ran longer than {} seconds", max.as_secs()),
+            Budget::ToolCalls(max) => write!(f, "reached the limit of {} tool calls", max),
+        }
+    }
+}
+
+impl AgentStatus {
+    /// Turn a budget exhaustion into an error so the process exits non-zero
+    pub fn into_result(self) -> Result<Self, CoderError> {
+        match self {
+            AgentStatus::BudgetExhausted(budget) => {
+                Err(CoderError::BudgetExhausted(budget.to_string()))
+            }
+            status => Ok(status),
+        }
+    }
+}
+
+/// Body of a chat completion request, as the Inference Gateway expects it
+#[derive(Serialize)]
+struct ChatCompletionRequest<'a> {
+    model: &'a str,
+    messages: &'a [Message],
+    stream: bool,
+    tools: &'a [Tool],
+    max_tokens: i32,
+}
+
+/// Error body the Inference Gateway returns alongside a failure status
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+/// Chat/tool loop shared by every command that drives the model
+///
+/// Requests are sent with a plain HTTP client rather than the SDK client, which hides the
+/// response status and headers needed to decide whether and when to retry.
+pub struct Agent<'a> {
+    client: reqwest::Client,
+    tools: Vec<Tool>,
+    conversation: Conversation,
+    provider: Provider,
+    model: String,
+    config: &'a Config,
+    scm: &'a dyn ScmProvider,
+    /// Directory the tools work in and the files they changed
+    workspace: Workspace,
+    iterations: usize,
+    tool_calls: usize,
+}
+
+/// Conversation of the agent currently running, logged when the process panics
+static CURRENT_CONVERSATION: Mutex<Option<Conversation>> = Mutex::new(None);
+
+/// Log the conversation of the running agent on panic
+///
+/// Installed once at startup; every run replaces the conversation it logs, so the hooks of
+/// earlier runs never chain up in auto mode.
+pub fn setup_panic_handler() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |panic_info| {
+        if let Ok(conversation) = CURRENT_CONVERSATION.try_lock() {
+            if let Some(conversation) = conversation.as_ref() {
+                info!("{:?}", conversation);
+            }
+        }
+
+        default_hook(panic_info);
+    }));
+}
+
+/// Replace the conversation the panic hook logs
+fn set_current_conversation(conversation: Option<Conversation>) {
+    if let Ok(mut current) = CURRENT_CONVERSATION.lock() {
+        *current = conversation;
+    }
+}
+
+impl<'a> Agent<'a> {
+    /// Create an agent for the configured provider and model
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Project configuration
+    /// * `scm` - SCM provider the tools talk to
+    /// * `tools` - Tools offered to the model
+    pub fn new(
+        config: &'a Config,
+        scm: &'a dyn ScmProvider,
+        tools: Vec<Tool>,
+    ) -> Result<Self, CoderError> {
+        let provider = Provider::try_from(config.agent.provider.as_str())?;
+        let model = config.agent.model.clone();
+
+        let conversation = Conversation::new(model.clone(), provider, config.agent.max_tokens);
+
+        Ok(Self {
+            client: reqwest::Client::new(),
+            tools,
+            conversation,
+            provider,
+            model,
+            config,
+            scm,
+            workspace: Workspace::new(&std::env::current_dir()?),
+            iterations: 0,
+            tool_calls: 0,
+        })
+    }
+
+    /// Run the tools in the worktree of the run instead of the current directory
+    pub fn with_worktree(mut self, worktree: &Worktree) -> Self {
+        self.workspace = Workspace::for_worktree(worktree);
+        self
+    }
+
+    pub fn system(&mut self, content: String) {
+        self.conversation.add_message(Message {
+            role: MessageRole::System,
+            content,
+            ..Default::default()
+        });
+    }
+
+    pub fn user(&mut self, content: String) {
+        self.conversation.add_message(Message {
+            role: MessageRole::User,
+            content,
+            ..Default::default()
+        });
+    }
+
+    /// Run the conversation until the model calls `done`, stops responding or a budget runs out
+    pub async fn run(&mut self) -> Result<AgentStatus, CoderError> {
+        let started = Instant::now();
+        let max_wall_time = Duration::from_secs(self.config.agent.max_wall_time);
+
+        info!("Starting AI Coder agent...");
+        info!("Press Ctrl+C to stop the agent.");
+        let status = self.run_loop(started, max_wall_time).await;
+        set_current_conversation(None);
+        let status = status?;
+
+        match &status {
+            AgentStatus::BudgetExhausted(budget) => warn!("Agent stopped: {}", budget),
+            status => info!("Agent stopped: {:?}", status),
+        }
+        info!(
+            "Run summary: {} iterations, {} tool calls, {} seconds",
+            self.iterations,
+            self.tool_calls,
+            started.elapsed().as_secs()
+        );
+
+        Ok(status)
+    }
+
+    async fn run_loop(
+        &mut self,
+        started: Instant,
+        max_wall_time: Duration,
+    ) -> Result<AgentStatus, CoderError> {
+        loop {
+            if self.iterations >= self.config.agent.max_iterations {
+                return Ok(AgentStatus::BudgetExhausted(Budget::Iterations(
+                    self.config.agent.max_iterations,
+                )));
+            }
+            let remaining = match max_wall_time.checked_sub(started.elapsed()) {
+                Some(remaining) if !remaining.is_zero() => remaining,
+                _ => {
+                    return Ok(AgentStatus::BudgetExhausted(Budget::WallTime(
+                        max_wall_time,
+                    )))
+                }
+            };
+            self.iterations += 1;
+            set_current_conversation(Some(self.conversation.clone()));
+
+            let request = self.generate_with_retry(self.conversation.clone().try_into()?);
+            let resp = match tokio::time::timeout(remaining, request).await {
+                Ok(resp) => resp?,
+                Err(_) => {
+                    return Ok(AgentStatus::BudgetExhausted(Budget::WallTime(
+                        max_wall_time,
+                    )))
+                }
+            };
+
+            let choice = match resp.choices.first() {
+                Some(choice) => choice,
+                None => {
+                    warn!("No response from the assistant. Exiting...");
+                    return Ok(AgentStatus::NoResponse);
+                }
+            };
+
+            let assistant_message = match utils::strip_thinking(&choice.message.content) {
+                Some(message) => message.trim().to_string(),
+                None => {
+                    warn!("Assistant message is empty. Exiting...");
+                    return Ok(AgentStatus::EmptyResponse);
+                }
+            };
+
+            self.conversation.add_message(Message {
+                role: MessageRole::Assistant,
+                content: assistant_message.clone(),
+                tool_calls: choice.message.tool_calls.clone(),
+                ..Default::default()
+            });
+
+            info!("Assistant: {}", assistant_message);
+            info!(
+                "Current tokens usage: {}",
+                self.conversation.get_current_tokens()?
+            );
+
+            if let Some(tool_calls) = &choice.message.tool_calls {
+                for tool_call in tool_calls {
+                    if self.tool_calls >= self.config.agent.max_tool_calls {
+                        return Ok(AgentStatus::BudgetExhausted(Budget::ToolCalls(
+                            self.config.agent.max_tool_calls,
+                        )));
+                    }
+                    if started.elapsed() >= max_wall_time {
+                        return Ok(AgentStatus::BudgetExhausted(Budget::WallTime(
+                            max_wall_time,
+                        )));
+                    }
+                    self.tool_calls += 1;
+
+                    if self.handle_tool_call(tool_call).await? {
+                        info!("Task completed. Exiting...");
+                        return Ok(AgentStatus::Completed);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Call the gateway, backing off and retrying while it is rate limited or unavailable
+    ///
+    /// A `Retry-After` header sent by the gateway takes precedence over the computed backoff.
+    async fn generate_with_retry(
+        &self,
+        messages: Vec<Message>,
+    ) -> Result<CreateChatCompletionResponse, CoderError> {
+        let retry = self
+            .config
+            .api
+            .retry_for(&self.provider.to_string())
+            .clone();
+
+        let mut attempt = 0;
+        loop {
+            let (error, retryable, retry_after) = match self.generate(&messages).await {
+                Ok(response) if response.status().is_success() => {
+                    return Ok(response.json().await?)
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    let retry_after = retry_after(response.headers());
+                    let body = response.text().await.unwrap_or_default();
+                    (
+                        gateway_error(status, &body),
+                        is_retryable(status),
+                        retry_after,
+                    )
+                }
+                Err(e) => {
+                    let retryable = e.is_timeout() || e.is_connect();
+                    (CoderError::HttpError(e), retryable, None)
+                }
+            };
+            if !retryable || attempt >= retry.max_retries {
+                return Err(error);
+            }
+
+            let delay = retry_after.unwrap_or_else(|| backoff(&retry, attempt));
+            warn!(
+                "Gateway request failed ({}), retrying in {}ms ({}/{})",
+                error,
+                delay.as_millis(),
+                attempt + 1,
+                retry.max_retries
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Send a single chat completion request
+    async fn generate(&self, messages: &[Message]) -> Result<reqwest::Response, reqwest::Error> {
+        let url = format!(
+            "{}/chat/completions?provider={}",
+            self.config.api.endpoint, self.provider
+        );
+        let request = ChatCompletionRequest {
+            model: &self.model,
+            messages,
+            stream: false,
+            tools: &self.tools,
+            max_tokens: 900,
+        };
+
+        self.client.post(url).json(&request).send().await
+    }
+
+    /// Execute a single tool call and feed the result back to the model.
+    ///
+    /// Tool failures are reported to the model so it can retry, they never abort the run.
+    /// Returns `true` once the model signals the task is done.
+    async fn handle_tool_call(
+        &mut self,
+        tool_call: &ChatCompletionMessageToolCall,
+    ) -> Result<bool, CoderError> {
+        let tool_result = match tools::Tools::from_str(tool_call.function.name.as_str()) {
+            Ok(tool) => match tool_call.function.parse_arguments() {
+                Ok(args) => {
+                    tools::handle_tool_calls(
+                        &tool,
+                        Some(args),
+                        &self.workspace,
+                        self.config,
+                        self.scm,
+                    )
+                    .await
+                }
+                Err(e) => Err(e.into()),
+            },
+            Err(e) => Err(e),
+        };
+
+        let (content, user_message, completed) = match tool_result {
+            Ok(result) => {
+                debug!("Tool result: {}", result);
+
+                let status: StatusResponse = serde_json::from_value(result.clone())?;
+                let completed = status.message == Some("Task completed".to_string());
+
+                let user_message = if status.retry {
+                    warn!("Tool requires retry");
+                    "Something went wrong can you retry it?"
+                } else {
+                    "Let's proceed to the next step."
+                };
+
+                (result.to_string(), user_message, completed)
+            }
+            Err(e) => {
+                warn!("Tool {} failed to execute: {}", tool_call.function.name, e);
+                (
+                    e.to_string(),
+                    "Something went wrong can you retry it? maybe take a step back.",
+                    false,
+                )
+            }
+        };
+
+        let tool_message = Message {
+            role: MessageRole::Tool,
+            content,
+            tool_call_id: Some(tool_call.id.clone()),
+            ..Default::default()
+        };
+        debug!("Tool message: {:?}", tool_message);
+        self.conversation.add_message(tool_message);
+
+        if completed {
+            return Ok(true);
+        }
+
+        let user_message = Message {
+            role: MessageRole::User,
+            content: user_message.to_string(),
+            ..Default::default()
+        };
+        debug!("User message: {:?}", user_message);
+        self.conversation.add_message(user_message);
+
+        Ok(false)
+    }
+}
+
+/// Rate limiting (429) and server errors (5xx) are worth retrying, whatever the body says
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Delay requested by a `Retry-After` header, given either in seconds or as an HTTP date
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+
+    match value.parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => httpdate::parse_http_date(value)
+            .ok()
+            .map(|date| date.duration_since(SystemTime::now()).unwrap_or_default()),
+    }
+}
+
+/// Map a failure status to the gateway error, using the JSON error message when there is one
+fn gateway_error(status: StatusCode, body: &str) -> CoderError {
+    let message = serde_json::from_str::<ErrorResponse>(body)
+        .map(|response| response.error)
+        .unwrap_or_else(|_| body.trim().to_string());
+
+    let error = match status {
+        StatusCode::BAD_REQUEST => GatewayError::BadRequest(message),
+        StatusCode::UNAUTHORIZED => GatewayError::Unauthorized(message),
+        status if status.is_server_error() => {
+            GatewayError::InternalError(format!("{}: {}", status, message))
+        }
+        status => GatewayError::Other(Box::new(std::io::Error::other(format!(
+            "Unexpected status code: {}: {}",
+            status, message
+        )))),
+    };
+    CoderError::InferenceGatewayError(error)
+}
+
+/// Exponential backoff starting at `initial_backoff_ms`, capped at `max_backoff_ms`
+fn backoff(retry: &RetryConfig, attempt: u32) -> Duration {
+    let delay = retry
+        .initial_backoff_ms
+        .saturating_mul(2u64.saturating_pow(attempt));
+    Duration::from_millis(delay.min(retry.max_backoff_ms))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scm::fake::InMemoryScm;
+
+    fn completion(message: &str) -> String {
+        format!(
+            r#"{{
+                "id": "chatcmpl-123",
+                "object": "chat.completion",
+                "created": 1630000000,
+                "model": "deepseek-r1-distill-llama-70b",
+                "choices": [{}]
+            }}"#,
+            message
+        )
+    }
+
+    fn test_config(endpoint: String) -> Config {
+        let mut config = Config::default();
+        config.api.endpoint = endpoint;
+        config
+    }
+
+    #[tokio::test]
+    async fn test_run_completes_on_done() -> Result<(), Box<dyn std::error::Error>> {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/chat/completions?provider=groq")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(completion(
+                r#"{
+                    "index": 0,
+                    "finish_reason": "tool_calls",
+                    "message": {
+                        "role": "assistant",
+                        "content": "<think>All good</think>Finished.",
+                        "tool_calls": [{
+                            "id": "call_1",
+                            "type": "function",
+                            "function": { "name": "done", "arguments": "{}" }
+                        }]
+                    }
+                }"#,
+            ))
+            .create_async()
+            .await;
+
+        let config = test_config(server.url());
+        let scm = InMemoryScm::default();
+        let mut agent = Agent::new(&config, &scm, tools::get_tools())?;
+        agent.system("You are a test".to_string());
+        agent.user("Finish".to_string());
+
+        assert_eq!(agent.run().await?, AgentStatus::Completed);
+        mock.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_stops_without_choices() -> Result<(), Box<dyn std::error::Error>> {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/chat/completions?provider=groq")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(completion(""))
+            .create_async()
+            .await;
+
+        let config = test_config(server.url());
+        let scm = InMemoryScm::default();
+        let mut agent = Agent::new(&config, &scm, tools::get_tools())?;
+        agent.user("Hello".to_string());
+
+        assert_eq!(agent.run().await?, AgentStatus::NoResponse);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_stops_when_iterations_are_exhausted() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let mut config = Config::default();
+        config.agent.max_iterations = 0;
+        let scm = InMemoryScm::default();
+        let mut agent = Agent::new(&config, &scm, tools::get_tools())?;
+        agent.user("Hello".to_string());
+
+        let status = agent.run().await?;
+        assert_eq!(status, AgentStatus::BudgetExhausted(Budget::Iterations(0)));
+        assert!(matches!(
+            status.into_result(),
+            Err(CoderError::BudgetExhausted(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_stops_when_wall_time_is_exhausted() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let mut config = Config::default();
+        config.agent.max_wall_time = 0;
+        let scm = InMemoryScm::default();
+        let mut agent = Agent::new(&config, &scm, tools::get_tools())?;
+        agent.user("Hello".to_string());
+
+        assert_eq!(
+            agent.run().await?,
+            AgentStatus::BudgetExhausted(Budget::WallTime(Duration::from_secs(0)))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_stops_when_tool_calls_are_exhausted() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/chat/completions?provider=groq")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(completion(
+                r#"{
+                    "index": 0,
+                    "finish_reason": "tool_calls",
+                    "message": {
+                        "role": "assistant",
+                        "content": "",
+                        "tool_calls": [
+                            {
+                                "id": "call_1",
+                                "type": "function",
+                                "function": { "name": "not_a_tool", "arguments": "{}" }
+                            },
+                            {
+                                "id": "call_2",
+                                "type": "function",
+                                "function": { "name": "done", "arguments": "{}" }
+                            }
+                        ]
+                    }
+                }"#,
+            ))
+            .create_async()
+            .await;
+
+        let mut config = test_config(server.url());
+        config.agent.max_tool_calls = 1;
+        let scm = InMemoryScm::default();
+        let mut agent = Agent::new(&config, &scm, tools::get_tools())?;
+        agent.user("Hello".to_string());
+
+        assert_eq!(
+            agent.run().await?,
+            AgentStatus::BudgetExhausted(Budget::ToolCalls(1))
+        );
+        assert_eq!(agent.tool_calls, 1);
+        mock.assert_async().await;
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(is_retryable(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(is_retryable(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(!is_retryable(StatusCode::NOT_FOUND));
+        assert!(!is_retryable(StatusCode::UNAUTHORIZED));
+        assert!(!is_retryable(StatusCode::BAD_REQUEST));
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+
+        headers.insert(reqwest::header::RETRY_AFTER, "3".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(3)));
+
+        headers.insert(
+            reqwest::header::RETRY_AFTER,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+
+        let later = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(120));
+        headers.insert(reqwest::header::RETRY_AFTER, later.parse().unwrap());
+        assert!(retry_after(&headers).is_some_and(|delay| delay > Duration::from_secs(100)));
+
+        headers.insert(reqwest::header::RETRY_AFTER, "soon".parse().unwrap());
+        assert_eq!(retry_after(&headers), None);
+    }
+
+    #[test]
+    fn test_backoff_is_exponential_and_capped() {
+        let retry = RetryConfig {
+            max_retries: 10,
+            initial_backoff_ms: 100,
+            max_backoff_ms: 1000,
+        };
+        assert_eq!(backoff(&retry, 0), Duration::from_millis(100));
+        assert_eq!(backoff(&retry, 1), Duration::from_millis(200));
+        assert_eq!(backoff(&retry, 3), Duration::from_millis(800));
+        assert_eq!(backoff(&retry, 4), Duration::from_millis(1000));
+        assert_eq!(backoff(&retry, 64), Duration::from_millis(1000));
+    }
+
+    #[tokio::test]
+    async fn test_run_retries_rate_limited_requests() -> Result<(), Box<dyn std::error::Error>> {
+        let mut server = mockito::Server::new_async().await;
+        let rate_limited = server
+            .mock("POST", "/chat/completions?provider=groq")
+            .with_status(429)
+            .expect(2)
+            .create_async()
+            .await;
+        let unavailable = server
+            .mock("POST", "/chat/completions?provider=groq")
+            .with_status(500)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"error":"upstream overloaded"}"#)
+            .expect(1)
+            .create_async()
+            .await;
+        let completed = server
+            .mock("POST", "/chat/completions?provider=groq")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(completion(
+                r#"{
+                    "index": 0,
+                    "finish_reason": "tool_calls",
+                    "message": {
+                        "role": "assistant",
+                        "content": "",
+                        "tool_calls": [{
+                            "id": "call_1",
+                            "type": "function",
+                            "function": { "name": "done", "arguments": "{}" }
+                        }]
+                    }
+                }"#,
+            ))
+            .expect(1)
+            .create_async()
+            .await;
+
+        let mut config = test_config(server.url());
+        config.api.provider_retry.insert(
+            "groq".to_string(),
+            RetryConfig {
+                max_retries: 3,
+                initial_backoff_ms: 1,
+                max_backoff_ms: 5,
+            },
+        );
+        let scm = InMemoryScm::default();
+        let mut agent = Agent::new(&config, &scm, tools::get_tools())?;
+        agent.user("Hello".to_string());
+
+        assert_eq!(agent.run().await?, AgentStatus::Completed);
+        rate_limited.assert_async().await;
+        unavailable.assert_async().await;
+        completed.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_honours_retry_after_and_retries_plain_text_errors(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut server = mockito::Server::new_async().await;
+        let rate_limited = server
+            .mock("POST", "/chat/completions?provider=groq")
+            .with_status(429)
+            .with_header("retry-after", "1")
+            .expect(1)
+            .create_async()
+            .await;
+        let unavailable = server
+            .mock("POST", "/chat/completions?provider=groq")
+            .with_status(503)
+            .with_header("content-type", "text/plain")
+            .with_body("Service Unavailable")
+            .expect(1)
+            .create_async()
+            .await;
+        let completed = server
+            .mock("POST", "/chat/completions?provider=groq")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(completion(
+                r#"{
+                    "index": 0,
+                    "finish_reason": "tool_calls",
+                    "message": {
+                        "role": "assistant",
+                        "content": "",
+                        "tool_calls": [{
+                            "id": "call_1",
+                            "type": "function",
+                            "function": { "name": "done", "arguments": "{}" }
+                        }]
+                    }
+                }"#,
+            ))
+            .expect(1)
+            .create_async()
+            .await;
+
+        let mut config = test_config(server.url());
+        config.api.retry = RetryConfig {
+            max_retries: 2,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 5,
+        };
+        let scm = InMemoryScm::default();
+        let mut agent = Agent::new(&config, &scm, tools::get_tools())?;
+        agent.user("Hello".to_string());
+
+        let started = Instant::now();
+        assert_eq!(agent.run().await?, AgentStatus::Completed);
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        rate_limited.assert_async().await;
+        unavailable.assert_async().await;
+        completed.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_gives_up_after_max_retries() -> Result<(), Box<dyn std::error::Error>> {
+        let mut server = mockito::Server::new_async().await;
+        let rate_limited = server
+            .mock("POST", "/chat/completions?provider=groq")
+            .with_status(429)
+            .expect(3)
+            .create_async()
+            .await;
+
+        let mut config = test_config(server.url());
+        config.api.retry = RetryConfig {
+            max_retries: 2,
+            initial_backoff_ms: 1,
+            max_backoff_ms: 5,
+        };
+        let scm = InMemoryScm::default();
+        let mut agent = Agent::new(&config, &scm, tools::get_tools())?;
+        agent.user("Hello".to_string());
+
+        let result = agent.run().await;
+        assert!(matches!(
+            result,
+            Err(CoderError::InferenceGatewayError(GatewayError::Other(_)))
+        ));
+        rate_limited.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tool_errors_are_reported_to_the_model() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let config = Config::default();
+        let scm = InMemoryScm::default();
+        let mut agent = Agent::new(&config, &scm, tools::get_tools())?;
+
+        let tool_call: ChatCompletionMessageToolCall = serde_json::from_value(serde_json::json!({
+            "id": "call_1",
+            "type": "function",
+            "function": { "name": "not_a_tool", "arguments": "{}" }
+        }))?;
+
+        assert!(!agent.handle_tool_call(&tool_call).await?);
+
+        let messages: Vec<Message> = agent.conversation.clone().try_into()?;
+        let tool_message = &messages[messages.len() - 2];
+        assert_eq!(tool_message.role, MessageRole::Tool);
+        assert!(tool_message.content.contains("Invalid tool: not_a_tool"));
+        assert_eq!(messages.last().unwrap().role, MessageRole::User);
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path};
+
+use crate::config;
+use crate::errors::CoderError;
+use crate::scm::{IssueFilter, ScmProvider};
+
+/// File under `.coder` that remembers the issues `coder auto` already worked on
+pub const PROCESSED_FILE: &str = "processed.yaml";
+
+/// Runs for an issue before `coder auto` gives up on it
+pub const MAX_ATTEMPTS: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Done,
+    Failed,
+}
+
+/// Outcome of the last run for an issue and how many runs there were
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Processed {
+    pub outcome: Outcome,
+    pub attempts: u32,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ProcessedIssues {
+    #[serde(default)]
+    issues: BTreeMap<u64, Processed>,
+}
+
+impl ProcessedIssues {
+    /// Load the processed issues, starting empty when the file does not exist yet
+    pub fn load(path: &Path) -> Result<Self, CoderError> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)?;
+        Ok(serde_yaml::from_str(&content)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), CoderError> {
+        fs::write(path, serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Whether the issue is done, or failed `MAX_ATTEMPTS` times and is not retried anymore
+    pub fn is_settled(&self, issue: u64) -> bool {
+        self.issues.get(&issue).is_some_and(|processed| {
+            processed.outcome == Outcome::Done || processed.attempts >= MAX_ATTEMPTS
+        })
+    }
+
+    /// Record the outcome of a run, counting the attempts made for the issue
+    ///
+    /// # Returns
+    ///
+    /// * `u32` - Number of runs made for the issue so far
+    pub fn record(&mut self, issue: u64, outcome: Outcome) -> u32 {
+        let attempts = self.issues.get(&issue).map_or(0, |p| p.attempts) + 1;
+        self.issues.insert(issue, Processed { outcome, attempts });
+        attempts
+    }
+}
+
+/// Let the people following an issue know the agent could not fix it
+///
+/// # Arguments
+///
+/// * `scm` - SCM provider the issue lives on
+/// * `issue` - Number of the issue
+/// * `reason` - Why the run failed
+/// * `label` - Label to add to the issue, if one is configured
+pub async fn report_failure(
+    scm: &dyn ScmProvider,
+    issue: u64,
+    reason: &str,
+    label: Option<&str>,
+) -> Result<(), CoderError> {
+    scm.comment(
+        issue,
+        &format!("Coder could not fix this issue: {}", reason),
+    )
+    .await?;
+
+    if let Some(label) = label {
+        scm.add_labels(issue, &[label.to_string()]).await?;
+    }
+    Ok(())
+}
+
+/// Issues matching the labels when given, otherwise the issues assigned to the authenticated user
+pub async fn issue_filter(
+    scm: &dyn ScmProvider,
+    labels: Option<&str>,
+) -> Result<IssueFilter, CoderError> {
+    let labels = labels.map(config::split_list).unwrap_or_default();
+    if !labels.is_empty() {
+        return Ok(IssueFilter {
+            assignee: None,
+            labels,
+        });
+    }
+
+    Ok(IssueFilter {
+        assignee: Some(scm.current_user().await?),
+        labels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scm::fake::InMemoryScm;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_processed_issues_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        let path = dir.path().join(PROCESSED_FILE);
+
+        let mut processed = ProcessedIssues::load(&path)?;
+        assert!(!processed.is_settled(1));
+
+        processed.record(1, Outcome::Done);
+        processed.record(2, Outcome::Failed);
+        processed.save(&path)?;
+
+        let processed = ProcessedIssues::load(&path)?;
+        assert!(processed.is_settled(1));
+        assert!(!processed.is_settled(2));
+        assert!(!processed.is_settled(3));
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_issues_are_retried_until_max_attempts() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let dir = tempdir()?;
+        let path = dir.path().join(PROCESSED_FILE);
+        let mut processed = ProcessedIssues::load(&path)?;
+
+        for attempt in 1..MAX_ATTEMPTS {
+            assert_eq!(processed.record(1, Outcome::Failed), attempt);
+            assert!(!processed.is_settled(1));
+        }
+        processed.save(&path)?;
+
+        let mut processed = ProcessedIssues::load(&path)?;
+        assert_eq!(processed.record(1, Outcome::Failed), MAX_ATTEMPTS);
+        assert!(processed.is_settled(1));
+
+        assert_eq!(processed.record(2, Outcome::Failed), 1);
+        assert_eq!(processed.record(2, Outcome::Done), 2);
+        assert!(processed.is_settled(2));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_report_failure() -> Result<(), Box<dyn std::error::Error>> {
+        let scm = InMemoryScm::default();
+
+        report_failure(&scm, 1, "budget exhausted", Some("coder-failed")).await?;
+        report_failure(&scm, 2, "push rejected", None).await?;
+
+        assert_eq!(
+            *scm.comments.lock().unwrap(),
+            vec![
+                (
+                    1,
+                    "Coder could not fix this issue: budget exhausted".to_string()
+                ),
+                (
+                    2,
+                    "Coder could not fix this issue: push rejected".to_string()
+                ),
+            ]
+        );
+        assert_eq!(
+            *scm.labels.lock().unwrap(),
+            vec![(1, "coder-failed".to_string())]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_issue_filter() -> Result<(), Box<dyn std::error::Error>> {
+        let scm = InMemoryScm::default();
+
+        let filter = issue_filter(&scm, Some("bug, coder ,,")).await?;
+        assert_eq!(filter.assignee, None);
+        assert_eq!(filter.labels, vec!["bug".to_string(), "coder".to_string()]);
+
+        let filter = issue_filter(&scm, None).await?;
+        assert_eq!(filter.assignee, Some("coder-bot".to_string()));
+        assert!(filter.labels.is_empty());
+        Ok(())
+    }
+}
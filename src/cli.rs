@@ -45,7 +45,19 @@ pub enum Commands {
     /// Pulls the issues from Github or Gitlab that assigned to the Bot.
     /// Runs the AI coder agent to generate a potential fix or development.
     /// Progress the issue to the next stage as it is being fixed or developed
-    Auto {},
+    Auto {
+        /// Seconds to wait between checks for new issues
+        #[arg(long, default_value_t = 300)]
+        interval: u64,
+
+        /// Stop after processing this many issues (0 means no limit)
+        #[arg(long, default_value_t = 0)]
+        limit: usize,
+
+        /// Comma separated labels to pick issues by instead of assignment (e.g. bug,coder)
+        #[arg(long)]
+        labels: Option<String>,
+    },
 
     /// Refactor look on potential improvement to the project
     /// and interact with the user for further actions.
@@ -155,4 +167,50 @@ mod tests {
             "Issue number cannot be negative"
         );
     }
+
+    #[test]
+    fn test_auto_command_defaults() {
+        let cli = Cli::parse_from(["coder", "auto"]);
+
+        match cli.command {
+            Commands::Auto {
+                interval,
+                limit,
+                labels,
+            } => {
+                assert_eq!(interval, 300);
+                assert_eq!(limit, 0);
+                assert_eq!(labels, None);
+            }
+            _ => panic!("Expected Auto command"),
+        }
+    }
+
+    #[test]
+    fn test_auto_command_with_options() {
+        let args = [
+            "coder",
+            "auto",
+            "--interval",
+            "60",
+            "--limit",
+            "2",
+            "--labels",
+            "bug,coder",
+        ];
+        let cli = Cli::parse_from(args);
+
+        match cli.command {
+            Commands::Auto {
+                interval,
+                limit,
+                labels,
+            } => {
+                assert_eq!(interval, 60);
+                assert_eq!(limit, 2);
+                assert_eq!(labels, Some("bug,coder".to_string()));
+            }
+            _ => panic!("Expected Auto command"),
+        }
+    }
 }
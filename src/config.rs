@@ -1,6 +1,6 @@
 use log::error;
 use serde::{Deserialize, Serialize};
-use std::{fs, path::Path};
+use std::{collections::BTreeMap, fs, path::Path};
 
 use crate::errors::CoderError;
 
@@ -15,10 +15,90 @@ pub struct Config {
 #[derive(Debug, Deserialize, Serialize)]
 pub struct LanguageConfig {
     pub name: String,
-    pub analyse: String,
-    pub linter: String,
-    pub test_command: String,
+    pub analyse: CommandConfig,
+    pub linter: CommandConfig,
+    pub test_command: CommandConfig,
+    /// Command running a subset of the tests, `{filter}` is replaced by the quoted filter
+    /// (e.g. cargo test {filter}). Uses the env, cwd and timeout of `test_command`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_filter: Option<String>,
+    /// Program and arguments the commands are passed to, e.g. [sh, -c]
+    #[serde(default = "default_shell")]
+    pub shell: Vec<String>,
+    /// Seconds after which a command without its own timeout is killed
+    #[serde(default = "default_command_timeout")]
+    pub command_timeout: u64,
+    /// Docs page for a term, `{term}` is replaced by the term, otherwise it is appended as a path
     pub docs_url: String,
+    /// Directories with vendored docs (e.g. docs, target/doc) searched by `docs_reference`
+    #[serde(default = "default_docs_paths")]
+    pub docs_paths: Vec<String>,
+}
+
+fn default_docs_paths() -> Vec<String> {
+    vec!["docs".to_string(), "target/doc".to_string()]
+}
+
+fn default_shell() -> Vec<String> {
+    vec!["sh".to_string(), "-c".to_string()]
+}
+
+fn default_command_timeout() -> u64 {
+    600
+}
+
+/// A configured command, either a plain command line or one with options
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum CommandConfig {
+    Command(String),
+    Options(CommandOptions),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CommandOptions {
+    /// Command line, run by the language shell so quoting, pipes and && chains work
+    pub command: String,
+    /// Environment variables set for the command
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Working directory relative to the repository root
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Seconds after which the command is killed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+}
+
+impl CommandConfig {
+    /// The command with its options, defaults for a plain command line
+    pub fn options(&self) -> CommandOptions {
+        match self {
+            CommandConfig::Command(command) => CommandOptions {
+                command: command.clone(),
+                env: BTreeMap::new(),
+                cwd: None,
+                timeout: None,
+            },
+            CommandConfig::Options(options) => options.clone(),
+        }
+    }
+
+    /// Replace the command line, keeping the options
+    fn with_command(self, command: String) -> Self {
+        match self {
+            CommandConfig::Command(_) => CommandConfig::Command(command),
+            CommandConfig::Options(options) => {
+                CommandConfig::Options(CommandOptions { command, ..options })
+            }
+        }
+    }
+}
+
+impl From<&str> for CommandConfig {
+    fn from(command: &str) -> Self {
+        CommandConfig::Command(command.to_string())
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -27,6 +107,57 @@ pub struct ScmConfig {
     pub owner: String,
     pub repository: String,
     pub issue_template: Option<String>,
+    /// Base URL of a self-hosted SCM instance (e.g. https://gitlab.example.com)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Branch pull requests target, detected from the HEAD of `remote` when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_branch: Option<String>,
+    /// Git remote of the repository
+    #[serde(default = "default_remote")]
+    pub remote: String,
+    /// Fork the branches are pushed to when the agent cannot push to the repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fork: Option<ForkConfig>,
+    /// Regex the subject line of every commit message has to match
+    #[serde(default = "default_commit_pattern")]
+    pub commit_pattern: String,
+    /// Label `coder auto` adds to the issues it gave up on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed_label: Option<String>,
+}
+
+fn default_remote() -> String {
+    "origin".to_string()
+}
+
+// Conventional commits, e.g. "fix(parser): handle empty input"
+fn default_commit_pattern() -> String {
+    r"^(build|chore|ci|docs|feat|fix|perf|refactor|revert|style|test)(\([\w./-]+\))?!?: \S.*$"
+        .to_string()
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ForkConfig {
+    /// Owner (on GitLab the namespace) of the fork, it has the same name as the repository
+    pub owner: String,
+    /// Git remote of the fork
+    pub remote: String,
+}
+
+impl ScmConfig {
+    /// Remote the branches of pull requests are pushed to
+    pub fn push_remote(&self) -> &str {
+        self.fork.as_ref().map_or(&self.remote, |fork| &fork.remote)
+    }
+
+    /// Head of a pull request for the branch, qualified with the fork owner (owner:branch)
+    pub fn head(&self, branch: &str) -> String {
+        match &self.fork {
+            Some(fork) => format!("{}:{}", fork.owner, branch),
+            None => branch.to_string(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -34,11 +165,76 @@ pub struct AgentConfig {
     pub model: String,
     pub provider: String,
     pub max_tokens: Option<usize>,
+    /// Maximum number of model round trips per run
+    #[serde(default = "default_max_iterations")]
+    pub max_iterations: usize,
+    /// Maximum wall-clock seconds per run
+    #[serde(default = "default_max_wall_time")]
+    pub max_wall_time: u64,
+    /// Maximum number of tool calls executed per run
+    #[serde(default = "default_max_tool_calls")]
+    pub max_tool_calls: usize,
+    /// Files the agent is allowed to change
+    #[serde(default)]
+    pub scope: ScopeConfig,
+}
+
+/// Glob patterns (relative to the repository root) limiting the files the agent may change
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ScopeConfig {
+    /// Patterns of files that may be changed, everything is allowed when empty
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Patterns of files that must never be changed, takes precedence over `allow`
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+fn default_max_iterations() -> usize {
+    50
+}
+
+fn default_max_wall_time() -> u64 {
+    1800
+}
+
+fn default_max_tool_calls() -> usize {
+    100
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ApiConfig {
     pub endpoint: String,
+    /// Backoff applied when the gateway is rate limited or unavailable
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// Per-provider overrides of `retry`, keyed by provider name (e.g. groq)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub provider_retry: BTreeMap<String, RetryConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RetryConfig {
+    pub max_retries: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            initial_backoff_ms: 1000,
+            max_backoff_ms: 60000,
+        }
+    }
+}
+
+impl ApiConfig {
+    /// Retry settings for the given provider, falling back to the global ones
+    pub fn retry_for(&self, provider: &str) -> &RetryConfig {
+        self.provider_retry.get(provider).unwrap_or(&self.retry)
+    }
 }
 
 impl Default for Config {
@@ -46,10 +242,14 @@ impl Default for Config {
         Self {
             language: LanguageConfig {
                 name: "rust".to_string(),
-                linter: "cargo fmt".to_string(),
-                analyse: "cargo clippy".to_string(),
-                test_command: "cargo test".to_string(),
+                linter: "cargo fmt".into(),
+                analyse: "cargo clippy".into(),
+                test_command: "cargo test".into(),
+                test_filter: Some("cargo test {filter}".to_string()),
+                shell: default_shell(),
+                command_timeout: default_command_timeout(),
                 docs_url: "https://docs.rs".to_string(),
+                docs_paths: default_docs_paths(),
             },
             scm: ScmConfig {
                 name: "".to_string(),
@@ -65,14 +265,26 @@ impl Default for Config {
                     ]
                     .join("\n"),
                 ),
+                url: None,
+                base_branch: None,
+                remote: default_remote(),
+                fork: None,
+                commit_pattern: default_commit_pattern(),
+                failed_label: None,
             },
             agent: AgentConfig {
                 provider: "groq".to_string(),
                 model: "deepseek-r1-distill-llama-70b".to_string(),
                 max_tokens: Some(4000),
+                max_iterations: default_max_iterations(),
+                max_wall_time: default_max_wall_time(),
+                max_tool_calls: default_max_tool_calls(),
+                scope: ScopeConfig::default(),
             },
             api: ApiConfig {
                 endpoint: "http://localhost:8080".to_string(),
+                retry: RetryConfig::default(),
+                provider_retry: BTreeMap::new(),
             },
         }
     }
@@ -85,6 +297,14 @@ pub fn default_config() -> String {
     }
 }
 
+// Split a comma separated list (e.g. glob patterns, paths or labels)
+pub(crate) fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
 // Load config from a file with environment variables if exists
 pub fn load(path: &Path) -> Result<Config, CoderError> {
     let config_content = fs::read_to_string(path)?;
@@ -92,14 +312,32 @@ pub fn load(path: &Path) -> Result<Config, CoderError> {
 
     // Language settings
     config.language.name = std::env::var("CODER_LANGUAGE_NAME").unwrap_or(config.language.name);
-    config.language.analyse =
-        std::env::var("CODER_LANGUAGE_ANALYSE").unwrap_or(config.language.analyse);
-    config.language.linter =
-        std::env::var("CODER_LANGUAGE_LINTER").unwrap_or(config.language.linter);
-    config.language.test_command =
-        std::env::var("CODER_LANGUAGE_TEST_COMMAND").unwrap_or(config.language.test_command);
+    if let Ok(analyse) = std::env::var("CODER_LANGUAGE_ANALYSE") {
+        config.language.analyse = config.language.analyse.with_command(analyse);
+    }
+    if let Ok(linter) = std::env::var("CODER_LANGUAGE_LINTER") {
+        config.language.linter = config.language.linter.with_command(linter);
+    }
+    if let Ok(test_command) = std::env::var("CODER_LANGUAGE_TEST_COMMAND") {
+        config.language.test_command = config.language.test_command.with_command(test_command);
+    }
+    if let Ok(test_filter) = std::env::var("CODER_LANGUAGE_TEST_FILTER") {
+        config.language.test_filter = Some(test_filter).filter(|t| !t.trim().is_empty());
+    }
+    if let Ok(shell) = std::env::var("CODER_LANGUAGE_SHELL") {
+        config.language.shell = shell.split_whitespace().map(String::from).collect();
+    }
+    if let Ok(command_timeout) = std::env::var("CODER_LANGUAGE_COMMAND_TIMEOUT") {
+        match command_timeout.parse() {
+            Ok(command_timeout) => config.language.command_timeout = command_timeout,
+            Err(_) => error!("Invalid CODER_LANGUAGE_COMMAND_TIMEOUT value"),
+        }
+    }
     config.language.docs_url =
         std::env::var("CODER_LANGUAGE_DOCS_URL").unwrap_or(config.language.docs_url);
+    if let Ok(docs_paths) = std::env::var("CODER_LANGUAGE_DOCS_PATHS") {
+        config.language.docs_paths = split_list(&docs_paths);
+    }
 
     // API settings
     config.api.endpoint =
@@ -110,6 +348,18 @@ pub fn load(path: &Path) -> Result<Config, CoderError> {
     config.scm.name = std::env::var("CODER_SCM_NAME").unwrap_or(config.scm.name);
     config.scm.owner = std::env::var("CODER_SCM_USERNAME").unwrap_or(config.scm.owner);
     config.scm.repository = std::env::var("CODER_SCM_REPOSITORY").unwrap_or(config.scm.repository);
+    if let Ok(url) = std::env::var("CODER_SCM_URL") {
+        config.scm.url = Some(url);
+    }
+    if let Ok(base_branch) = std::env::var("CODER_SCM_BASE_BRANCH") {
+        config.scm.base_branch = Some(base_branch).filter(|b| !b.trim().is_empty());
+    }
+    config.scm.remote = std::env::var("CODER_SCM_REMOTE").unwrap_or(config.scm.remote);
+    config.scm.commit_pattern =
+        std::env::var("CODER_SCM_COMMIT_PATTERN").unwrap_or(config.scm.commit_pattern);
+    if let Ok(failed_label) = std::env::var("CODER_SCM_FAILED_LABEL") {
+        config.scm.failed_label = Some(failed_label).filter(|l| !l.trim().is_empty());
+    }
 
     // Agent settings
     config.agent.provider = std::env::var("CODER_AGENT_PROVIDER").unwrap_or(config.agent.provider);
@@ -120,6 +370,30 @@ pub fn load(path: &Path) -> Result<Config, CoderError> {
             Err(_) => error!("Invalid CODER_AGENT_MAX_TOKENS value"),
         }
     }
+    if let Ok(max_iterations) = std::env::var("CODER_AGENT_MAX_ITERATIONS") {
+        match max_iterations.parse() {
+            Ok(max_iterations) => config.agent.max_iterations = max_iterations,
+            Err(_) => error!("Invalid CODER_AGENT_MAX_ITERATIONS value"),
+        }
+    }
+    if let Ok(max_wall_time) = std::env::var("CODER_AGENT_MAX_WALL_TIME") {
+        match max_wall_time.parse() {
+            Ok(max_wall_time) => config.agent.max_wall_time = max_wall_time,
+            Err(_) => error!("Invalid CODER_AGENT_MAX_WALL_TIME value"),
+        }
+    }
+    if let Ok(max_tool_calls) = std::env::var("CODER_AGENT_MAX_TOOL_CALLS") {
+        match max_tool_calls.parse() {
+            Ok(max_tool_calls) => config.agent.max_tool_calls = max_tool_calls,
+            Err(_) => error!("Invalid CODER_AGENT_MAX_TOOL_CALLS value"),
+        }
+    }
+    if let Ok(allow) = std::env::var("CODER_AGENT_SCOPE_ALLOW") {
+        config.agent.scope.allow = split_list(&allow);
+    }
+    if let Ok(deny) = std::env::var("CODER_AGENT_SCOPE_DENY") {
+        config.agent.scope.deny = split_list(&deny);
+    }
 
     Ok(config)
 }
@@ -141,16 +415,107 @@ mod tests {
     fn test_default_config() {
         let config = Config::default();
         assert_eq!(config.language.name, "rust");
-        assert_eq!(config.language.linter, "cargo fmt");
-        assert_eq!(config.language.analyse, "cargo clippy");
-        assert_eq!(config.language.test_command, "cargo test");
+        assert_eq!(config.language.linter.options().command, "cargo fmt");
+        assert_eq!(config.language.analyse.options().command, "cargo clippy");
+        assert_eq!(config.language.test_command.options().command, "cargo test");
+        assert_eq!(config.language.shell, vec!["sh", "-c"]);
+        assert_eq!(config.language.command_timeout, 600);
+        assert_eq!(
+            config.language.test_filter,
+            Some("cargo test {filter}".to_string())
+        );
         assert_eq!(config.language.docs_url, "https://docs.rs");
+        assert_eq!(config.language.docs_paths, vec!["docs", "target/doc"]);
 
         assert_eq!(config.agent.provider, "groq");
         assert_eq!(config.agent.model, "deepseek-r1-distill-llama-70b");
         assert_eq!(config.agent.max_tokens, Some(4000));
+        assert_eq!(config.agent.max_iterations, 50);
+        assert_eq!(config.agent.max_wall_time, 1800);
+        assert!(config.agent.scope.allow.is_empty());
+        assert_eq!(config.agent.max_tool_calls, 100);
 
         assert_eq!(config.api.endpoint, "http://localhost:8080");
+        assert_eq!(config.api.retry.max_retries, 5);
+    }
+
+    #[test]
+    fn test_retry_for_provider() {
+        let config_content = r#"---
+language:
+  name: "rust"
+  analyse: "cargo clippy"
+  linter: "cargo fmt"
+  test_command: "cargo test"
+  docs_url: "https://docs.rs"
+scm:
+  name: "github"
+  owner: "test"
+  repository: "test"
+agent:
+  model: "default-model"
+  provider: "groq"
+api:
+  endpoint: "http://localhost:8080"
+  retry:
+    max_retries: 3
+    initial_backoff_ms: 500
+    max_backoff_ms: 5000
+  provider_retry:
+    groq:
+      max_retries: 10
+      initial_backoff_ms: 2000
+      max_backoff_ms: 120000
+"#;
+        let config_file = create_test_config_file(config_content);
+        let config = load(config_file.path()).unwrap();
+
+        assert_eq!(config.api.retry_for("groq").max_retries, 10);
+        assert_eq!(config.api.retry_for("ollama").max_retries, 3);
+        assert_eq!(config.api.retry_for("ollama").initial_backoff_ms, 500);
+    }
+
+    #[test]
+    #[serial]
+    fn test_command_options() {
+        let config_content = r#"---
+language:
+  name: "rust"
+  analyse: "cargo clippy"
+  linter: "cargo fmt --check"
+  test_command:
+    command: cargo test -- --nocapture "my test"
+    env:
+      RUST_BACKTRACE: "1"
+    cwd: crates/core
+    timeout: 600
+  docs_url: "https://docs.rs"
+scm:
+  name: "github"
+  owner: "test"
+  repository: "test"
+agent:
+  model: "default-model"
+  provider: "groq"
+api:
+  endpoint: "http://localhost:8080"
+"#;
+        let config_file = create_test_config_file(config_content);
+        env::set_var("CODER_LANGUAGE_TEST_COMMAND", "cargo nextest run");
+
+        let config = load(config_file.path()).unwrap();
+        env::remove_var("CODER_LANGUAGE_TEST_COMMAND");
+
+        assert_eq!(
+            config.language.linter,
+            CommandConfig::Command("cargo fmt --check".to_string())
+        );
+        let test = config.language.test_command.options();
+        assert_eq!(test.command, "cargo nextest run");
+        assert_eq!(test.env.get("RUST_BACKTRACE"), Some(&"1".to_string()));
+        assert_eq!(test.cwd, Some("crates/core".to_string()));
+        assert_eq!(test.timeout, Some(600));
+        assert_eq!(config.language.docs_paths, vec!["docs", "target/doc"]);
     }
 
     #[test]
@@ -178,20 +543,37 @@ api:
 
         env::set_var("CODER_LANGUAGE_NAME", "python");
         env::set_var("CODER_SCM_NAME", "gitlab");
+        env::set_var("CODER_SCM_URL", "https://gitlab.example.com");
         env::set_var("CODER_AGENT_MODEL", "new-model");
         env::set_var("CODER_AGENT_MAX_TOKENS", "2000");
+        env::set_var("CODER_AGENT_MAX_ITERATIONS", "10");
+        env::set_var("CODER_AGENT_SCOPE_ALLOW", "src/**, tests/**");
 
         let config = load(config_file.path()).unwrap();
 
         assert_eq!(config.language.name, "python");
         assert_eq!(config.scm.name, "gitlab");
+        assert_eq!(
+            config.scm.url,
+            Some("https://gitlab.example.com".to_string())
+        );
         assert_eq!(config.agent.model, "new-model");
         assert_eq!(config.agent.max_tokens, Some(2000));
+        assert_eq!(config.agent.max_iterations, 10);
+        assert_eq!(config.agent.max_wall_time, 1800);
+        assert_eq!(
+            config.agent.scope.allow,
+            vec!["src/**".to_string(), "tests/**".to_string()]
+        );
+        assert!(config.agent.scope.deny.is_empty());
 
         env::remove_var("CODER_LANGUAGE_NAME");
         env::remove_var("CODER_SCM_NAME");
+        env::remove_var("CODER_SCM_URL");
         env::remove_var("CODER_AGENT_MODEL");
         env::remove_var("CODER_AGENT_MAX_TOKENS");
+        env::remove_var("CODER_AGENT_MAX_ITERATIONS");
+        env::remove_var("CODER_AGENT_SCOPE_ALLOW");
     }
 
     #[test]
@@ -115,7 +115,7 @@ impl fmt::Debug for Conversation {
 #[cfg(test)]
 mod tests {
     use crate::conversation::Message;
-    use crate::MessageRole;
+    use inference_gateway_sdk::MessageRole;
 
     #[test]
     fn test_message_tool_call_id_serialization() {
@@ -0,0 +1,550 @@
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::HashSet, sync::LazyLock};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+/// A lint, analysis or test finding normalized across tools
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+static RUSTC_HEADER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(error|warning)(?:\[(\w+)\])?: (.+)$").unwrap());
+static RUSTC_LOCATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\s*--> (.+?):(\d+):\d+$").unwrap());
+static PYTEST_SUMMARY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(FAILED|ERROR) ([^\s:]+)(?:::(\S+))?(?: - (.+))?$").unwrap());
+static LIBTEST_RESULT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^test (\S+)(?: - should panic)? \.\.\. FAILED$").unwrap());
+// "panicked at src/lib.rs:12:5:" since Rust 1.73, "panicked at 'message', src/lib.rs:12:5" before
+static LIBTEST_PANIC: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^thread '([^']+)' panicked at (?:'(.*)', )?(.+?):(\d+):\d+:?$").unwrap()
+});
+static PYTEST_LOCATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(\S+\.py):(\d+): (\w+(?:Error|Exception)\b.*)$").unwrap());
+
+/// Parse the output of a lint, analysis or test command
+///
+/// Every known format is tried, so mixed output (e.g. cargo JSON on stdout and rustc
+/// diagnostics on stderr) yields the findings of both.
+///
+/// # Arguments
+///
+/// * `output` - Combined stdout and stderr of the command
+///
+/// # Returns
+///
+/// * `Vec<Diagnostic>` - Findings in the order they were reported
+pub fn parse(output: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = parse_cargo_json(output);
+    diagnostics.extend(parse_rustc(output));
+    diagnostics.extend(parse_eslint_json(output));
+    diagnostics.extend(parse_libtest(output));
+    diagnostics.extend(parse_pytest(output));
+    // The report may follow other output on either stream, so parsing starts where it does
+    if let Some(start) = output.find("<?xml").or_else(|| output.find("<testsuite")) {
+        diagnostics.extend(parse_junit(&output[start..]));
+    }
+
+    let mut seen = HashSet::with_capacity(diagnostics.len());
+    diagnostics.retain(|diagnostic| seen.insert(diagnostic.clone()));
+    diagnostics
+}
+
+fn severity(level: &str) -> Option<Severity> {
+    match level {
+        "error" | "error: internal compiler error" => Some(Severity::Error),
+        "warning" => Some(Severity::Warning),
+        "note" | "help" => Some(Severity::Note),
+        _ => None,
+    }
+}
+
+/// `cargo ... --message-format=json` compiler messages
+pub fn parse_cargo_json(output: &str) -> Vec<Diagnostic> {
+    output
+        .lines()
+        .filter(|line| line.starts_with('{'))
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|value| value["reason"] == "compiler-message")
+        .filter_map(|value| {
+            let message = &value["message"];
+            let severity = severity(message["level"].as_str()?)?;
+            let span = message["spans"]
+                .as_array()
+                .and_then(|spans| spans.iter().find(|s| s["is_primary"] == true));
+            Some(Diagnostic {
+                file: span.and_then(|s| s["file_name"].as_str()).map(String::from),
+                line: span
+                    .and_then(|s| s["line_start"].as_u64())
+                    .map(|l| l as usize),
+                severity,
+                message: message["message"].as_str()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Human readable rustc diagnostics, as printed by cargo build/clippy/test
+pub fn parse_rustc(output: &str) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+    let mut pending: Option<Diagnostic> = None;
+
+    for line in output.lines() {
+        if let Some(captures) = RUSTC_HEADER.captures(line) {
+            diagnostics.extend(pending.take());
+            let message = match captures.get(2) {
+                Some(code) => format!("{} [{}]", &captures[3], code.as_str()),
+                None => captures[3].to_string(),
+            };
+            // Summaries such as "aborting due to 2 previous errors" carry no location
+            pending = Some(Diagnostic {
+                file: None,
+                line: None,
+                severity: severity(&captures[1]).unwrap_or(Severity::Error),
+                message,
+            });
+        } else if let Some(captures) = RUSTC_LOCATION.captures(line) {
+            if let Some(diagnostic) = pending.as_mut().filter(|d| d.file.is_none()) {
+                diagnostic.file = Some(captures[1].to_string());
+                diagnostic.line = captures[2].parse().ok();
+            }
+        }
+    }
+    diagnostics.extend(pending);
+
+    diagnostics.retain(|d| {
+        d.file.is_some()
+            || !(d.message.starts_with("aborting due to")
+                || d.message.starts_with("could not compile")
+                || d.message.contains("generated") && d.message.contains("warning"))
+    });
+    diagnostics
+}
+
+/// `eslint --format json` output
+pub fn parse_eslint_json(output: &str) -> Vec<Diagnostic> {
+    let Some(start) = output.find("[{") else {
+        return Vec::new();
+    };
+    let Ok(files) = serde_json::from_str::<Vec<Value>>(output[start..].trim_end()) else {
+        return Vec::new();
+    };
+
+    files
+        .iter()
+        .filter_map(|file| Some((file["filePath"].as_str()?, file["messages"].as_array()?)))
+        .flat_map(|(path, messages)| {
+            messages.iter().filter_map(move |message| {
+                let text = message["message"].as_str()?;
+                Some(Diagnostic {
+                    file: Some(path.to_string()),
+                    line: message["line"].as_u64().map(|l| l as usize),
+                    severity: if message["severity"] == 2 {
+                        Severity::Error
+                    } else {
+                        Severity::Warning
+                    },
+                    message: match message["ruleId"].as_str() {
+                        Some(rule) => format!("{} [{}]", text, rule),
+                        None => text.to_string(),
+                    },
+                })
+            })
+        })
+        .collect()
+}
+
+/// `cargo test` (libtest) failed tests, located where they panicked when the output says so
+pub fn parse_libtest(output: &str) -> Vec<Diagnostic> {
+    let mut failed = Vec::new();
+    let mut panics = Vec::new();
+    let mut lines = output.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        if let Some(captures) = LIBTEST_RESULT.captures(line) {
+            failed.push(captures[1].to_string());
+        } else if let Some(captures) = LIBTEST_PANIC.captures(line) {
+            // Since Rust 1.73 the message follows on the next line
+            let message = match captures.get(2) {
+                Some(message) => message.as_str().to_string(),
+                None => lines
+                    .next_if(|next| !next.trim().is_empty())
+                    .unwrap_or("panicked")
+                    .to_string(),
+            };
+            panics.push((
+                captures[1].to_string(),
+                Diagnostic {
+                    file: Some(captures[3].to_string()),
+                    line: captures[4].parse().ok(),
+                    severity: Severity::Error,
+                    message: format!("{}: {}", &captures[1], message),
+                },
+            ));
+        }
+    }
+
+    failed
+        .into_iter()
+        .map(
+            |test| match panics.iter().position(|(thread, _)| *thread == test) {
+                Some(index) => panics.remove(index).1,
+                None => Diagnostic {
+                    file: None,
+                    line: None,
+                    severity: Severity::Error,
+                    message: format!("{}: failed", test),
+                },
+            },
+        )
+        .collect()
+}
+
+/// pytest short test summary and traceback locations
+pub fn parse_pytest(output: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for line in output.lines() {
+        if let Some(captures) = PYTEST_SUMMARY.captures(line) {
+            let test = captures.get(3).map(|t| t.as_str()).unwrap_or_default();
+            let reason = captures.get(4).map(|r| r.as_str()).unwrap_or("failed");
+            diagnostics.push(Diagnostic {
+                file: Some(captures[2].to_string()),
+                line: None,
+                severity: Severity::Error,
+                message: if test.is_empty() {
+                    reason.to_string()
+                } else {
+                    format!("{}: {}", test, reason)
+                },
+            });
+        } else if let Some(captures) = PYTEST_LOCATION.captures(line) {
+            diagnostics.push(Diagnostic {
+                file: Some(captures[1].to_string()),
+                line: captures[2].parse().ok(),
+                severity: Severity::Error,
+                message: captures[3].to_string(),
+            });
+        }
+    }
+    diagnostics
+}
+
+fn attribute(element: &BytesStart, name: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|a| a.key.as_ref() == name)
+        .and_then(|a| a.unescape_value().ok())
+        .map(|v| v.to_string())
+}
+
+/// JUnit XML reports, as written by most test runners (pytest --junitxml, jest-junit, ...)
+pub fn parse_junit(xml: &str) -> Vec<Diagnostic> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut diagnostics = Vec::new();
+    let mut testcase: Option<(String, Option<String>, Option<usize>)> = None;
+    let mut failure: Option<Diagnostic> = None;
+
+    loop {
+        let (element, is_empty) = match reader.read_event() {
+            Ok(Event::Start(e)) => (e, false),
+            Ok(Event::Empty(e)) => (e, true),
+            Ok(Event::Text(text)) => {
+                // Without a message attribute the first line of the body describes the failure
+                if let Some(diagnostic) = failure.as_mut().filter(|d| d.message.ends_with(": ")) {
+                    let body = text.unescape().unwrap_or_default();
+                    diagnostic
+                        .message
+                        .push_str(body.lines().next().unwrap_or_default());
+                }
+                continue;
+            }
+            Ok(Event::End(e)) => {
+                match e.name().as_ref() {
+                    b"failure" | b"error" => diagnostics.extend(failure.take()),
+                    b"testcase" => testcase = None,
+                    _ => {}
+                }
+                continue;
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => continue,
+        };
+
+        match element.name().as_ref() {
+            b"testcase" => {
+                let name = attribute(&element, b"name").unwrap_or_default();
+                let name = match attribute(&element, b"classname") {
+                    Some(class) if !class.is_empty() => format!("{}.{}", class, name),
+                    _ => name,
+                };
+                testcase = (!is_empty).then(|| {
+                    (
+                        name,
+                        attribute(&element, b"file"),
+                        attribute(&element, b"line").and_then(|l| l.parse().ok()),
+                    )
+                });
+            }
+            b"failure" | b"error" => {
+                let (name, file, line) = testcase.clone().unwrap_or_default();
+                let message = attribute(&element, b"message").unwrap_or_default();
+                let diagnostic = Diagnostic {
+                    file,
+                    line,
+                    severity: Severity::Error,
+                    message: format!("{}: {}", name, message),
+                };
+                if is_empty {
+                    diagnostics.push(diagnostic);
+                } else {
+                    failure = Some(diagnostic);
+                }
+            }
+            _ => {}
+        }
+    }
+    diagnostics.extend(failure);
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(file: &str, line: usize, severity: Severity, message: &str) -> Diagnostic {
+        Diagnostic {
+            file: Some(file.to_string()),
+            line: Some(line),
+            severity,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_cargo_json() {
+        let output = r#"{"reason":"compiler-artifact","package_id":"coder"}
+{"reason":"compiler-message","message":{"level":"warning","message":"unused variable: `x`","spans":[{"file_name":"src/main.rs","line_start":3,"is_primary":true}]}}
+{"reason":"compiler-message","message":{"level":"error","message":"aborting due to 1 previous error","spans":[]}}
+{"reason":"build-finished","success":false}"#;
+
+        let diagnostics = parse_cargo_json(output);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            diagnostics[0],
+            diagnostic("src/main.rs", 3, Severity::Warning, "unused variable: `x`")
+        );
+        assert_eq!(diagnostics[1].file, None);
+    }
+
+    #[test]
+    fn test_parse_rustc() {
+        let output = r#"    Checking coder v0.1.5
+warning: unused import: `std::fs`
+ --> src/lib.rs:1:5
+  |
+1 | use std::fs;
+  |     ^^^^^^^
+error[E0308]: mismatched types
+  --> src/main.rs:10:12
+   |
+warning: `coder` (bin "coder") generated 1 warning
+error: could not compile `coder` (bin "coder") due to 1 previous error; 1 warning emitted
+"#;
+
+        assert_eq!(
+            parse_rustc(output),
+            vec![
+                diagnostic(
+                    "src/lib.rs",
+                    1,
+                    Severity::Warning,
+                    "unused import: `std::fs`"
+                ),
+                diagnostic(
+                    "src/main.rs",
+                    10,
+                    Severity::Error,
+                    "mismatched types [E0308]"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_eslint_json() {
+        let output = r#"[{"filePath":"/app/src/index.js","messages":[{"ruleId":"no-unused-vars","severity":2,"message":"'a' is defined but never used.","line":1,"column":7},{"ruleId":null,"severity":1,"message":"Unexpected console statement.","line":4}],"errorCount":1}]"#;
+
+        assert_eq!(
+            parse_eslint_json(output),
+            vec![
+                diagnostic(
+                    "/app/src/index.js",
+                    1,
+                    Severity::Error,
+                    "'a' is defined but never used. [no-unused-vars]"
+                ),
+                diagnostic(
+                    "/app/src/index.js",
+                    4,
+                    Severity::Warning,
+                    "Unexpected console statement."
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_libtest() {
+        let output = r#"running 4 tests
+test tests::test_ok ... ok
+test tests::test_add ... FAILED
+test tests::test_legacy ... FAILED
+test tests::test_should_panic - should panic ... FAILED
+
+failures:
+
+---- tests::test_add stdout ----
+
+thread 'tests::test_add' panicked at src/lib.rs:12:5:
+assertion `left == right` failed
+  left: 3
+ right: 4
+note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+
+---- tests::test_legacy stdout ----
+thread 'tests::test_legacy' panicked at 'called `Option::unwrap()` on a `None` value', src/parser.rs:40:18
+
+failures:
+    tests::test_add
+    tests::test_legacy
+    tests::test_should_panic
+
+test result: FAILED. 1 passed; 3 failed; 0 ignored; 0 measured; 0 filtered out"#;
+
+        assert_eq!(
+            parse_libtest(output),
+            vec![
+                diagnostic(
+                    "src/lib.rs",
+                    12,
+                    Severity::Error,
+                    "tests::test_add: assertion `left == right` failed"
+                ),
+                diagnostic(
+                    "src/parser.rs",
+                    40,
+                    Severity::Error,
+                    "tests::test_legacy: called `Option::unwrap()` on a `None` value"
+                ),
+                Diagnostic {
+                    file: None,
+                    line: None,
+                    severity: Severity::Error,
+                    message: "tests::test_should_panic: failed".to_string(),
+                },
+            ]
+        );
+
+        let output = "test tests::test_timeout ... FAILED\n";
+        assert_eq!(
+            parse_libtest(output),
+            vec![Diagnostic {
+                file: None,
+                line: None,
+                severity: Severity::Error,
+                message: "tests::test_timeout: failed".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_pytest() {
+        let output = r#"    def test_add():
+>       assert add(1, 2) == 4
+E       assert 3 == 4
+
+tests/test_math.py:5: AssertionError
+=========================== short test summary info ============================
+FAILED tests/test_math.py::test_add - assert 3 == 4
+ERROR tests/test_io.py - ModuleNotFoundError: No module named 'io2'
+========================= 1 failed, 1 error in 0.12s ==========================="#;
+
+        let diagnostics = parse_pytest(output);
+        assert_eq!(
+            diagnostics[0],
+            diagnostic("tests/test_math.py", 5, Severity::Error, "AssertionError")
+        );
+        assert_eq!(
+            diagnostics[1].message,
+            "test_add: assert 3 == 4".to_string()
+        );
+        assert_eq!(
+            diagnostics[2].message,
+            "ModuleNotFoundError: No module named 'io2'"
+        );
+    }
+
+    #[test]
+    fn test_parse_junit() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites>
+  <testsuite name="pytest" tests="3" failures="2">
+    <testcase classname="tests.test_math" name="test_ok" file="tests/test_math.py" line="1"/>
+    <testcase classname="tests.test_math" name="test_add" file="tests/test_math.py" line="4">
+      <failure message="assert 3 == 4">def test_add(): ...</failure>
+    </testcase>
+    <testcase classname="tests.test_io" name="test_read">
+      <error>FileNotFoundError: data.txt
+more details</error>
+    </testcase>
+  </testsuite>
+</testsuites>"#;
+
+        assert_eq!(
+            parse_junit(xml),
+            vec![
+                diagnostic(
+                    "tests/test_math.py",
+                    4,
+                    Severity::Error,
+                    "tests.test_math.test_add: assert 3 == 4"
+                ),
+                Diagnostic {
+                    file: None,
+                    line: None,
+                    severity: Severity::Error,
+                    message: "tests.test_io.test_read: FileNotFoundError: data.txt".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_mixed_output() {
+        let output = "warning: unused variable: `a`\n --> src/lib.rs:2:9\n\nFAILED tests/test_a.py::test_a - boom\n";
+        assert_eq!(parse(output).len(), 2);
+        assert!(parse("All good").is_empty());
+
+        // A JUnit report written to stderr after other output
+        let output = "Running 1 < 2 checks\n\n<?xml version=\"1.0\"?><testsuite><testcase name=\"t\"><failure message=\"boom\"/></testcase></testsuite>";
+        assert_eq!(parse(output)[0].message, "t: boom");
+    }
+}
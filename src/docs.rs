@@ -0,0 +1,302 @@
+use log::{debug, warn};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, sync::LazyLock, time::Duration};
+
+use crate::config::LanguageConfig;
+use crate::errors::CoderError;
+use crate::index;
+
+/// Maximum number of snippets returned for a term
+pub const MAX_SNIPPETS: usize = 5;
+
+/// Snippets longer than this are cut, so a single page cannot fill the context
+const MAX_SNIPPET_CHARS: usize = 1200;
+
+/// Vendored docs (e.g. `cargo doc` output) can be huge, stop scanning after this many files
+const MAX_LOCAL_FILES: usize = 2000;
+
+const MAX_FILE_BYTES: u64 = 512 * 1024;
+
+const DOC_EXTENSIONS: [&str; 5] = ["md", "markdown", "txt", "rst", "html"];
+
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A ranked piece of documentation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snippet {
+    /// File path relative to the repository root, or the URL it was fetched from
+    pub source: String,
+    pub score: usize,
+    pub text: String,
+}
+
+static SCRIPTS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)<(script|style|nav|header|footer)\b.*?</(script|style|nav|header|footer)>")
+        .unwrap()
+});
+static BLOCK_ENDS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)</(p|div|h[1-6]|li|pre|section|tr|table|ul|ol|details)>|<br\s*/?>").unwrap()
+});
+static TAGS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)<[^>]*>").unwrap());
+static BLANK_LINES: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\n\s*\n").unwrap());
+
+/// Reduce an HTML page to its text, keeping block boundaries as blank lines
+fn html_to_text(html: &str) -> String {
+    let text = SCRIPTS.replace_all(html, "");
+    let text = BLOCK_ENDS.replace_all(&text, "\n\n");
+    let text = TAGS.replace_all(&text, "");
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// Split a document into paragraphs and score them against the term
+///
+/// The full term counts most, each of its words adds to the score as well.
+fn rank(source: &str, text: &str, term: &str) -> Vec<Snippet> {
+    let term = term.to_lowercase();
+    let words: Vec<&str> = term.split_whitespace().filter(|w| w.len() > 1).collect();
+
+    BLANK_LINES
+        .split(text)
+        .map(str::trim)
+        .filter(|paragraph| !paragraph.is_empty())
+        .filter_map(|paragraph| {
+            let lower = paragraph.to_lowercase();
+            let score = lower.matches(term.as_str()).count() * 10
+                + words
+                    .iter()
+                    .map(|word| lower.matches(word).count())
+                    .sum::<usize>();
+            (score > 0).then(|| Snippet {
+                source: source.to_string(),
+                score,
+                text: paragraph.chars().take(MAX_SNIPPET_CHARS).collect(),
+            })
+        })
+        .collect()
+}
+
+/// Search the configured documentation directories
+fn search_local(root: &Path, dirs: &[String], term: &str) -> Vec<Snippet> {
+    let mut snippets = Vec::new();
+    let mut scanned = 0;
+
+    for dir in dirs {
+        let dir = root.join(dir);
+        if !dir.is_dir() {
+            continue;
+        }
+        // Vendored docs such as target/doc are usually gitignored
+        let walker = index::walk_builder(&dir)
+            .git_ignore(false)
+            .max_filesize(Some(MAX_FILE_BYTES))
+            .build();
+        for entry in walker.flatten() {
+            let path = entry.path();
+            let extension = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or_default()
+                .to_lowercase();
+            if !path.is_file() || !DOC_EXTENSIONS.contains(&extension.as_str()) {
+                continue;
+            }
+            if scanned == MAX_LOCAL_FILES {
+                warn!("Stopped scanning docs after {} files", MAX_LOCAL_FILES);
+                return snippets;
+            }
+            scanned += 1;
+
+            let Ok(content) = fs::read_to_string(path) else {
+                continue;
+            };
+            let text = if extension == "html" {
+                html_to_text(&content)
+            } else {
+                content
+            };
+            let source = path.strip_prefix(root).unwrap_or(path).to_string_lossy();
+            snippets.extend(rank(&source, &text, term));
+        }
+    }
+
+    snippets
+}
+
+/// Percent-encode a term for use in a URL path or query
+fn encode(term: &str) -> String {
+    term.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// URL to look the term up at
+///
+/// `docs_url` may contain a `{term}` placeholder (e.g. https://example.com/search?q={term}),
+/// otherwise the term is appended as a path segment (e.g. https://docs.rs/serde).
+pub fn docs_lookup_url(docs_url: &str, term: &str) -> String {
+    if docs_url.contains("{term}") {
+        docs_url.replace("{term}", &encode(term))
+    } else {
+        format!("{}/{}", docs_url.trim_end_matches('/'), encode(term))
+    }
+}
+
+/// Fetch the docs page for the term
+async fn search_remote(docs_url: &str, term: &str) -> Result<Vec<Snippet>, CoderError> {
+    let url = docs_lookup_url(docs_url, term);
+    debug!("Fetching documentation from {}", url);
+
+    let response = reqwest::Client::builder()
+        .timeout(HTTP_TIMEOUT)
+        .build()?
+        .get(&url)
+        .send()
+        .await?
+        .error_for_status()?;
+    let is_html = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("html"));
+    let body = response.text().await?;
+    let text = if is_html { html_to_text(&body) } else { body };
+
+    Ok(rank(&url, &text, term))
+}
+
+/// Look the term up in the local docs and the docs URL of the language
+///
+/// # Arguments
+///
+/// * `root` - Repository root the docs paths are relative to
+/// * `language` - Language config with `docs_paths` and `docs_url`
+/// * `term` - Term to look up
+///
+/// # Returns
+///
+/// * `Result<Vec<Snippet>, CoderError>` - The best snippets, highest score first. An unreachable
+///   docs URL is logged and skipped so local results are still returned.
+pub async fn lookup(
+    root: &Path,
+    language: &LanguageConfig,
+    term: &str,
+) -> Result<Vec<Snippet>, CoderError> {
+    if term.trim().is_empty() {
+        return Err(CoderError::InvalidArguments(
+            "Term cannot be empty".to_string(),
+        ));
+    }
+
+    let mut snippets = search_local(root, &language.docs_paths, term);
+    if !language.docs_url.trim().is_empty() {
+        match search_remote(&language.docs_url, term).await {
+            Ok(remote) => snippets.extend(remote),
+            Err(e) => warn!("Failed to fetch documentation for '{}': {}", term, e),
+        }
+    }
+
+    // Stable sort keeps local docs first on equal scores
+    snippets.sort_by_key(|s| std::cmp::Reverse(s.score));
+    snippets.truncate(MAX_SNIPPETS);
+    Ok(snippets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use tempfile::tempdir;
+
+    fn language(docs_url: String) -> LanguageConfig {
+        LanguageConfig {
+            docs_url,
+            docs_paths: vec!["docs".to_string()],
+            ..Config::default().language
+        }
+    }
+
+    #[test]
+    fn test_html_to_text() {
+        let html = "<html><head><style>p { color: red; }</style></head><body><h1>Vec</h1><p>A contiguous &lt;growable&gt; array.</p><script>alert(1)</script></body></html>";
+        let text = html_to_text(html);
+        assert!(text.contains("Vec\n\nA contiguous <growable> array."));
+        assert!(!text.contains("color"));
+        assert!(!text.contains("alert"));
+    }
+
+    #[test]
+    fn test_docs_lookup_url() {
+        assert_eq!(
+            docs_lookup_url("https://docs.rs/", "serde json"),
+            "https://docs.rs/serde%20json"
+        );
+        assert_eq!(
+            docs_lookup_url("https://example.com/search?q={term}", "Vec::push"),
+            "https://example.com/search?q=Vec%3A%3Apush"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lookup_ranks_local_and_remote_snippets() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let dir = tempdir()?;
+        std::fs::create_dir_all(dir.path().join("docs"))?;
+        std::fs::write(
+            dir.path().join("docs/config.md"),
+            "# Configuration\n\nThe retry section controls backoff.\n\nUnrelated paragraph.\n",
+        )?;
+
+        let mut server = mockito::Server::new_async().await;
+        let page = server
+            .mock("GET", "/retry")
+            .with_status(200)
+            .with_header("content-type", "text/html")
+            .with_body("<p>Call retry to retry the request.</p><p>Nothing to see.</p>")
+            .create_async()
+            .await;
+
+        let snippets = lookup(dir.path(), &language(server.url()), "retry").await?;
+
+        assert_eq!(snippets.len(), 2);
+        assert_eq!(snippets[0].source, format!("{}/retry", server.url()));
+        assert_eq!(snippets[0].text, "Call retry to retry the request.");
+        assert_eq!(snippets[1].source, "docs/config.md");
+        assert_eq!(snippets[1].text, "The retry section controls backoff.");
+        page.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lookup_tolerates_unavailable_docs_url() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let dir = tempdir()?;
+        std::fs::create_dir_all(dir.path().join("docs"))?;
+        std::fs::write(
+            dir.path().join("docs/guide.txt"),
+            "Use the index command.\n",
+        )?;
+
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/index")
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let snippets = lookup(dir.path(), &language(server.url()), "index").await?;
+        assert_eq!(snippets.len(), 1);
+        assert_eq!(snippets[0].source, "docs/guide.txt");
+        Ok(())
+    }
+}
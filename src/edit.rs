@@ -0,0 +1,297 @@
+use serde::{Deserialize, Serialize};
+
+use crate::errors::CoderError;
+
+/// Replace the single occurrence of `search` with `replace`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchReplace {
+    pub search: String,
+    pub replace: String,
+}
+
+/// Line ending of the content, CRLF when its first line ends with one
+fn line_ending(content: &str) -> &'static str {
+    match content.find('\n') {
+        Some(end) if content[..end].ends_with('\r') => "\r\n",
+        _ => "\n",
+    }
+}
+
+/// Apply search/replace blocks in order
+///
+/// Every block must match exactly once in the content produced by the previous blocks,
+/// otherwise nothing is applied. Blocks are written with `\n`, in a CRLF file they are
+/// matched and inserted with CRLF line endings.
+///
+/// # Arguments
+///
+/// * `content` - Original file content
+/// * `edits` - Blocks to apply
+///
+/// # Returns
+///
+/// * `Result<String, CoderError>` - The edited content, or `CoderError::EditError` naming the failing block
+pub fn apply_search_replace(content: &str, edits: &[SearchReplace]) -> Result<String, CoderError> {
+    let ending = line_ending(content);
+    let with_ending = |text: &str| match ending {
+        "\n" => text.to_string(),
+        ending => text.replace("\r\n", "\n").replace('\n', ending),
+    };
+
+    let mut result = content.to_string();
+    for (i, edit) in edits.iter().enumerate() {
+        let number = i + 1;
+        let (search, replace) = (with_ending(&edit.search), with_ending(&edit.replace));
+        if search.is_empty() {
+            return Err(CoderError::EditError(format!(
+                "edit {} has an empty search block",
+                number
+            )));
+        }
+        match result.matches(search.as_str()).count() {
+            0 => {
+                return Err(CoderError::EditError(format!(
+                    "edit {} did not match",
+                    number
+                )))
+            }
+            1 => result = result.replacen(search.as_str(), &replace, 1),
+            count => {
+                return Err(CoderError::EditError(format!(
+                    "edit {} matched {} times, include more surrounding lines to make it unique",
+                    number, count
+                )))
+            }
+        }
+    }
+    Ok(result)
+}
+
+#[derive(Debug, Default)]
+struct Hunk {
+    /// 1-based line the hunk starts at in the original file
+    old_start: usize,
+    old: Vec<String>,
+    new: Vec<String>,
+}
+
+fn parse_hunk_start(header: &str) -> Option<usize> {
+    // @@ -12,5 +12,6 @@ optional section heading
+    let old = header.strip_prefix("@@ -")?.split_whitespace().next()?;
+    old.split(',').next()?.parse().ok()
+}
+
+fn parse_unified_diff(diff: &str) -> Result<Vec<Hunk>, CoderError> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    for line in diff.lines() {
+        if line.starts_with("@@") {
+            let old_start = parse_hunk_start(line).ok_or_else(|| {
+                CoderError::EditError(format!(
+                    "hunk {} has an invalid header: {}",
+                    hunks.len() + 1,
+                    line
+                ))
+            })?;
+            hunks.push(Hunk {
+                old_start,
+                ..Default::default()
+            });
+            continue;
+        }
+
+        let Some(hunk) = hunks.last_mut() else {
+            // File headers (diff --git, ---, +++, index) before the first hunk
+            continue;
+        };
+        if let Some(context) = line.strip_prefix(' ') {
+            hunk.old.push(context.to_string());
+            hunk.new.push(context.to_string());
+        } else if let Some(removed) = line.strip_prefix('-') {
+            hunk.old.push(removed.to_string());
+        } else if let Some(added) = line.strip_prefix('+') {
+            hunk.new.push(added.to_string());
+        } else if line.is_empty() {
+            // Some generators drop the leading space of empty context lines
+            hunk.old.push(String::new());
+            hunk.new.push(String::new());
+        } else if !line.starts_with('\\') {
+            return Err(CoderError::EditError(format!(
+                "hunk {} has an invalid line: {}",
+                hunks.len(),
+                line
+            )));
+        }
+    }
+
+    if hunks.is_empty() {
+        return Err(CoderError::EditError(
+            "diff does not contain any hunks".to_string(),
+        ));
+    }
+    Ok(hunks)
+}
+
+/// Find `needle` in `lines` at or after `from`, preferring the position closest to `expected`
+fn find_lines(lines: &[String], needle: &[String], from: usize, expected: usize) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(expected.clamp(from, lines.len()));
+    }
+    if needle.len() > lines.len() {
+        return None;
+    }
+    (from..=lines.len() - needle.len())
+        .filter(|&i| lines[i..i + needle.len()] == *needle)
+        .min_by_key(|&i| i.abs_diff(expected))
+}
+
+/// Apply a unified diff to the content
+///
+/// Hunks are located by their context, so line numbers that drifted are tolerated.
+/// Nothing is applied unless every hunk matches. The line endings of the content and
+/// whether it ends with a newline are kept.
+///
+/// # Arguments
+///
+/// * `content` - Original file content
+/// * `diff` - Unified diff for a single file
+///
+/// # Returns
+///
+/// * `Result<String, CoderError>` - The patched content, or `CoderError::EditError` naming the failing hunk
+pub fn apply_unified_diff(content: &str, diff: &str) -> Result<String, CoderError> {
+    let hunks = parse_unified_diff(diff)?;
+    let mut lines: Vec<String> = content.lines().map(String::from).collect();
+
+    // Line numbers shift as earlier hunks add or remove lines
+    let mut offset: isize = 0;
+    let mut from = 0;
+    for (i, hunk) in hunks.iter().enumerate() {
+        let expected = (hunk.old_start.saturating_sub(1) as isize + offset).max(0) as usize;
+        let position = find_lines(&lines, &hunk.old, from, expected)
+            .ok_or_else(|| CoderError::EditError(format!("hunk {} did not match", i + 1)))?;
+
+        lines.splice(
+            position..position + hunk.old.len(),
+            hunk.new.iter().cloned(),
+        );
+        offset += hunk.new.len() as isize - hunk.old.len() as isize;
+        from = position + hunk.new.len();
+    }
+
+    let ending = line_ending(content);
+    let mut result = lines.join(ending);
+    if content.ends_with('\n') || (content.is_empty() && !result.is_empty()) {
+        result.push_str(ending);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(search: &str, replace: &str) -> SearchReplace {
+        SearchReplace {
+            search: search.to_string(),
+            replace: replace.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_apply_search_replace() -> Result<(), CoderError> {
+        let content = "fn main() {\n    println!(\"hi\");\n}\n";
+        let result = apply_search_replace(
+            content,
+            &[
+                edit("\"hi\"", "\"hello\""),
+                edit("fn main()", "pub fn main()"),
+            ],
+        )?;
+        assert_eq!(result, "pub fn main() {\n    println!(\"hello\");\n}\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_search_replace_failures() {
+        let content = "let a = 1;\nlet b = 1;\n";
+
+        let result = apply_search_replace(content, &[edit("a", "x"), edit("let c", "let d")]);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Edit failed: edit 2 did not match"
+        );
+
+        let result = apply_search_replace(content, &[edit("= 1", "= 2")]);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("edit 1 matched 2 times"));
+    }
+
+    #[test]
+    fn test_apply_unified_diff() -> Result<(), CoderError> {
+        let content = "one\ntwo\nthree\nfour\nfive\nsix\nseven\n";
+        let diff = "--- a/numbers.txt
++++ b/numbers.txt
+@@ -1,3 +1,3 @@
+ one
+-two
++2
+ three
+@@ -5,3 +5,4 @@
+ five
+ six
++six and a half
+ seven
+";
+        assert_eq!(
+            apply_unified_diff(content, diff)?,
+            "one\n2\nthree\nfour\nfive\nsix\nsix and a half\nseven\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_unified_diff_tolerates_drifted_line_numbers() -> Result<(), CoderError> {
+        let content = "header\nheader\none\ntwo\nthree\n";
+        let diff = "@@ -1,3 +1,3 @@\n one\n-two\n+2\n three\n";
+        assert_eq!(
+            apply_unified_diff(content, diff)?,
+            "header\nheader\none\n2\nthree\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_edits_keep_crlf_line_endings() -> Result<(), CoderError> {
+        let content = "fn a() {\r\n    one();\r\n}\r\n";
+
+        let result = apply_search_replace(
+            content,
+            &[edit("    one();\n}", "    one();\n    two();\n}")],
+        )?;
+        assert_eq!(result, "fn a() {\r\n    one();\r\n    two();\r\n}\r\n");
+
+        let diff = "@@ -1,3 +1,3 @@\n fn a() {\n-    one();\n+    1();\n }\n";
+        assert_eq!(
+            apply_unified_diff(content, diff)?,
+            "fn a() {\r\n    1();\r\n}\r\n"
+        );
+
+        let diff = "@@ -1,2 +1,2 @@\n-one\n+1\n two\n\\ No newline at end of file\n";
+        assert_eq!(apply_unified_diff("one\r\ntwo", diff)?, "1\r\ntwo");
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_unified_diff_reports_failing_hunk() {
+        let content = "one\ntwo\nthree\n";
+        let diff = "@@ -1,2 +1,2 @@\n-one\n+1\n two\n@@ -3 +3 @@\n-four\n+4\n";
+        assert_eq!(
+            apply_unified_diff(content, diff).unwrap_err().to_string(),
+            "Edit failed: hunk 2 did not match"
+        );
+
+        assert!(apply_unified_diff(content, "just text").is_err());
+    }
+}
@@ -14,12 +14,33 @@ pub enum CoderError {
     #[error("Missing arguments: {0}")]
     MissingArguments(String),
 
+    #[error("Invalid path: {0}")]
+    PathError(String),
+
+    #[error("Out of scope: {0}")]
+    OutOfScope(String),
+
+    #[error("Edit failed: {0}")]
+    EditError(String),
+
+    #[error("Invalid arguments: {0}")]
+    InvalidArguments(String),
+
+    #[error("Budget exhausted: {0}")]
+    BudgetExhausted(String),
+
     #[error("Tokenization error: {0}")]
     TokenizationError(String),
 
     #[error("GitHub API error: {0}")]
     GitHubError(#[from] octocrab::Error),
 
+    #[error("GitLab API error: {0}")]
+    GitLabError(String),
+
+    #[error("HTTP error: {0}")]
+    HttpError(#[from] reqwest::Error),
+
     #[error("IO error: {0}")]
     IOError(#[from] std::io::Error),
 
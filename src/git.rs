@@ -0,0 +1,510 @@
+use serde::{Deserialize, Serialize};
+use std::{path::Path, time::Duration};
+use tokio::process::Command;
+
+use crate::config::ScmConfig;
+use crate::errors::CoderError;
+use crate::process;
+
+/// Maximum number of bytes of diff output returned to the model
+pub const MAX_DIFF_BYTES: usize = 64 * 1024;
+
+/// Maximum number of commits returned by `log`
+pub const MAX_LOG_ENTRIES: usize = 100;
+
+/// Time after which git is killed, a push or fetch against an unreachable remote must not hang
+/// the agent
+const GIT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Bytes of stdout and stderr kept of a git command
+const MAX_GIT_OUTPUT_BYTES: usize = 8 * 1024 * 1024;
+
+/// Run git in `root`, failing with its stderr when it exits unsuccessfully
+///
+/// Git never prompts, stdin is closed and credential prompts are disabled, so missing
+/// credentials fail the command instead of waiting for input.
+pub async fn run(root: &Path, args: &[&str]) -> Result<String, CoderError> {
+    let output = output(root, args).await?;
+    if !output.success {
+        return Err(CoderError::GitError(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            output.stderr.trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+/// Run git in `root` and return its output whatever the exit code, failing only on a timeout
+async fn output(root: &Path, args: &[&str]) -> Result<process::Output, CoderError> {
+    let mut command = Command::new("git");
+    command
+        .current_dir(root)
+        .args(args)
+        .env("GIT_TERMINAL_PROMPT", "0");
+    let output = process::run(command, GIT_TIMEOUT, MAX_GIT_OUTPUT_BYTES)
+        .await
+        .map_err(|e| CoderError::GitError(e.to_string()))?;
+    if output.timed_out {
+        return Err(CoderError::GitError(format!(
+            "git {} timed out after {} seconds",
+            args.join(" "),
+            GIT_TIMEOUT.as_secs()
+        )));
+    }
+    Ok(output)
+}
+
+/// Whether git tracks the file, or any file under the directory
+pub async fn is_tracked(root: &Path, relative: &Path) -> Result<bool, CoderError> {
+    let path = relative.to_string_lossy();
+    Ok(!run(root, &["ls-files", "--", &path])
+        .await?
+        .trim()
+        .is_empty())
+}
+
+/// Default branch of a remote, from its locally known HEAD or else by asking the remote
+pub async fn default_branch(root: &Path, remote: &str) -> Result<String, CoderError> {
+    let head = format!("refs/remotes/{}/HEAD", remote);
+    if let Ok(branch) = run(root, &["symbolic-ref", "--short", &head]).await {
+        let prefix = format!("{}/", remote);
+        if let Some(branch) = branch.trim().strip_prefix(&prefix) {
+            return Ok(branch.to_string());
+        }
+    }
+
+    // Prints "ref: refs/heads/main\tHEAD" followed by the commit of HEAD
+    run(root, &["ls-remote", "--symref", remote, "HEAD"])
+        .await?
+        .lines()
+        .find_map(|line| {
+            line.strip_prefix("ref: refs/heads/")?
+                .strip_suffix("\tHEAD")
+        })
+        .map(String::from)
+        .ok_or_else(|| {
+            CoderError::GitError(format!("Could not detect the default branch of {}", remote))
+        })
+}
+
+/// Branch pull requests target, the configured `base_branch` or the default branch of the remote
+pub async fn base_branch(root: &Path, scm: &ScmConfig) -> Result<String, CoderError> {
+    match &scm.base_branch {
+        Some(branch) => Ok(branch.clone()),
+        None => default_branch(root, &scm.remote).await,
+    }
+}
+
+/// Commit a branch of a remote points to, `None` when the remote has no such branch
+pub async fn remote_branch(
+    root: &Path,
+    remote: &str,
+    branch: &str,
+) -> Result<Option<String>, CoderError> {
+    let reference = format!("refs/heads/{}", branch);
+    // Prints "<commit>\trefs/heads/<branch>"
+    Ok(run(root, &["ls-remote", remote, &reference])
+        .await?
+        .lines()
+        .find_map(|line| line.split_once('\t'))
+        .filter(|(_, name)| *name == reference)
+        .map(|(commit, _)| commit.to_string()))
+}
+
+/// Fetch a branch of a remote into its remote-tracking branch
+///
+/// # Returns
+///
+/// * `Result<String, CoderError>` - The commit the branch points to
+pub async fn fetch_branch(root: &Path, remote: &str, branch: &str) -> Result<String, CoderError> {
+    let tracking = format!("refs/remotes/{}/{}", remote, branch);
+    let refspec = format!("+refs/heads/{}:{}", branch, tracking);
+    run(root, &["fetch", "-q", remote, &refspec]).await?;
+    Ok(run(root, &["rev-parse", &tracking])
+        .await?
+        .trim()
+        .to_string())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Commit {
+    pub hash: String,
+    pub author: String,
+    pub date: String,
+    pub subject: String,
+}
+
+/// Recent commits, optionally limited to the ones touching a path
+///
+/// # Arguments
+///
+/// * `root` - Repository root
+/// * `path` - Optional file or directory relative to the root
+/// * `limit` - Number of commits, capped at `MAX_LOG_ENTRIES`
+///
+/// # Returns
+///
+/// * `Result<Vec<Commit>, CoderError>` - Commits, newest first
+pub async fn log(
+    root: &Path,
+    path: Option<&Path>,
+    limit: usize,
+) -> Result<Vec<Commit>, CoderError> {
+    let max_count = format!("--max-count={}", limit.clamp(1, MAX_LOG_ENTRIES));
+    let path = path.map(|p| p.to_string_lossy().to_string());
+    let mut args = vec![
+        "log",
+        &max_count,
+        "--date=short",
+        "--format=%h%x1f%an%x1f%ad%x1f%s",
+        "--",
+    ];
+    if let Some(path) = &path {
+        args.push(path);
+    }
+
+    Ok(run(root, &args)
+        .await?
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(4, '\x1f');
+            Some(Commit {
+                hash: fields.next()?.to_string(),
+                author: fields.next()?.to_string(),
+                date: fields.next()?.to_string(),
+                subject: fields.next()?.to_string(),
+            })
+        })
+        .collect())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlameLine {
+    /// 1-based line number in the current file
+    pub line: usize,
+    pub commit: String,
+    pub author: String,
+    /// Seconds since the Unix epoch
+    pub author_time: i64,
+    pub summary: String,
+    pub content: String,
+}
+
+/// Who last changed each line in a range
+///
+/// # Arguments
+///
+/// * `root` - Repository root
+/// * `path` - File relative to the root
+/// * `start_line` - First line (1-based)
+/// * `end_line` - Last line (inclusive)
+///
+/// # Returns
+///
+/// * `Result<Vec<BlameLine>, CoderError>` - One entry per line
+pub async fn blame(
+    root: &Path,
+    path: &Path,
+    start_line: usize,
+    end_line: usize,
+) -> Result<Vec<BlameLine>, CoderError> {
+    let range = format!("-L{},{}", start_line, end_line);
+    let output = run(
+        root,
+        &[
+            "blame",
+            "--line-porcelain",
+            &range,
+            "--",
+            &path.to_string_lossy(),
+        ],
+    )
+    .await?;
+
+    // Each line starts with "<commit> <original line> <final line>", followed by
+    // key/value headers and finally the content prefixed with a tab
+    let mut lines = Vec::new();
+    let mut current: Option<BlameLine> = None;
+    for row in output.lines() {
+        if let Some(content) = row.strip_prefix('\t') {
+            if let Some(mut line) = current.take() {
+                line.content = content.to_string();
+                lines.push(line);
+            }
+        } else if let Some(line) = current.as_mut() {
+            if let Some(author) = row.strip_prefix("author ") {
+                line.author = author.to_string();
+            } else if let Some(time) = row.strip_prefix("author-time ") {
+                line.author_time = time.parse().unwrap_or_default();
+            } else if let Some(summary) = row.strip_prefix("summary ") {
+                line.summary = summary.to_string();
+            }
+        } else {
+            let mut fields = row.split_whitespace();
+            let commit = fields.next().unwrap_or_default();
+            let line = fields
+                .nth(1)
+                .and_then(|l| l.parse().ok())
+                .unwrap_or_default();
+            current = Some(BlameLine {
+                line,
+                commit: commit.chars().take(12).collect(),
+                author: String::new(),
+                author_time: 0,
+                summary: String::new(),
+                content: String::new(),
+            });
+        }
+    }
+
+    Ok(lines)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Diff {
+    pub base: String,
+    pub diff: String,
+    /// Whether the diff was cut at `MAX_DIFF_BYTES`
+    pub truncated: bool,
+}
+
+/// Diff of the working tree (including uncommitted changes) against a base revision
+///
+/// Untracked files, such as the ones the agent created and did not commit yet, are shown as
+/// new files.
+///
+/// # Arguments
+///
+/// * `root` - Repository root
+/// * `base` - Revision to compare against, e.g. main
+/// * `path` - Optional file or directory relative to the root
+///
+/// # Returns
+///
+/// * `Result<Diff, CoderError>` - The unified diff, cut at `MAX_DIFF_BYTES`
+pub async fn diff(root: &Path, base: &str, path: Option<&Path>) -> Result<Diff, CoderError> {
+    let path = path.map(|p| p.to_string_lossy().to_string());
+    let mut args = vec!["diff", base, "--"];
+    if let Some(path) = &path {
+        args.push(path);
+    }
+
+    let mut diff = run(root, &args).await?;
+
+    let mut untracked = vec!["ls-files", "-z", "--others", "--exclude-standard", "--"];
+    if let Some(path) = &path {
+        untracked.push(path);
+    }
+    for file in run(root, &untracked).await?.split('\0') {
+        if file.is_empty() || diff.len() > MAX_DIFF_BYTES {
+            continue;
+        }
+        // Exits with 1 when the files differ, which a new file always does
+        let args = ["diff", "--no-index", "--", "/dev/null", file];
+        let output = output(root, &args).await?;
+        if !output.success && output.exit_code != Some(1) {
+            return Err(CoderError::GitError(format!(
+                "git {} failed: {}",
+                args.join(" "),
+                output.stderr.trim()
+            )));
+        }
+        diff.push_str(&output.stdout);
+    }
+
+    let truncated = diff.len() > MAX_DIFF_BYTES;
+    if truncated {
+        let mut end = MAX_DIFF_BYTES;
+        while !diff.is_char_boundary(end) {
+            end -= 1;
+        }
+        diff.truncate(end);
+    }
+
+    Ok(Diff {
+        base: base.to_string(),
+        diff,
+        truncated,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    async fn commit(root: &Path, message: &str) -> Result<(), CoderError> {
+        run(root, &["add", "."]).await?;
+        run(
+            root,
+            &[
+                "-c",
+                "user.name=Jane Doe",
+                "-c",
+                "user.email=jane@example.com",
+                "commit",
+                "-q",
+                "-m",
+                message,
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn repo() -> Result<tempfile::TempDir, Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        run(dir.path(), &["init", "-q", "-b", "main"]).await?;
+        fs::write(dir.path().join("lib.rs"), "fn a() {}\nfn b() {}\n")?;
+        fs::write(dir.path().join("README.md"), "# Readme\n")?;
+        commit(dir.path(), "Initial commit").await?;
+        fs::write(dir.path().join("lib.rs"), "fn a() {}\nfn b() -> u8 { 1 }\n")?;
+        commit(dir.path(), "Return a value from b").await?;
+        Ok(dir)
+    }
+
+    #[tokio::test]
+    async fn test_run_never_waits_for_input() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        // Reads stdin until it is closed, the hash is the one of empty content
+        let hash = run(dir.path(), &["hash-object", "--stdin"]).await?;
+        assert_eq!(hash.trim(), "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_default_branch() -> Result<(), Box<dyn std::error::Error>> {
+        let remote = repo().await?;
+        run(remote.path(), &["branch", "-q", "-m", "main", "develop"]).await?;
+        let dir = tempdir()?;
+        run(
+            dir.path(),
+            &[
+                "clone",
+                "-q",
+                &remote.path().to_string_lossy(),
+                &dir.path().to_string_lossy(),
+            ],
+        )
+        .await?;
+        assert_eq!(default_branch(dir.path(), "origin").await?, "develop");
+
+        // Without the locally known HEAD the remote is asked
+        run(dir.path(), &["remote", "set-head", "origin", "--delete"]).await?;
+        assert_eq!(default_branch(dir.path(), "origin").await?, "develop");
+
+        let mut scm = crate::config::Config::default().scm;
+        assert_eq!(base_branch(dir.path(), &scm).await?, "develop");
+        scm.base_branch = Some("release".to_string());
+        assert_eq!(base_branch(dir.path(), &scm).await?, "release");
+
+        assert!(default_branch(dir.path(), "upstream").await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fetch_remote_branch() -> Result<(), Box<dyn std::error::Error>> {
+        let remote = repo().await?;
+        run(remote.path(), &["branch", "-q", "coder/issue-1", "HEAD~1"]).await?;
+        let dir = tempdir()?;
+        run(dir.path(), &["init", "-q"]).await?;
+        run(
+            dir.path(),
+            &["remote", "add", "origin", &remote.path().to_string_lossy()],
+        )
+        .await?;
+
+        let expected = run(remote.path(), &["rev-parse", "HEAD~1"])
+            .await?
+            .trim()
+            .to_string();
+        assert_eq!(
+            remote_branch(dir.path(), "origin", "coder/issue-1").await?,
+            Some(expected.clone())
+        );
+        assert_eq!(remote_branch(dir.path(), "origin", "issue-1").await?, None);
+
+        assert_eq!(
+            fetch_branch(dir.path(), "origin", "coder/issue-1").await?,
+            expected
+        );
+        assert!(fetch_branch(dir.path(), "origin", "missing").await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_log() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = repo().await?;
+
+        let commits = log(dir.path(), None, 10).await?;
+        assert_eq!(commits.len(), 2);
+        assert_eq!(commits[0].subject, "Return a value from b");
+        assert_eq!(commits[0].author, "Jane Doe");
+
+        let commits = log(dir.path(), Some(Path::new("README.md")), 10).await?;
+        assert_eq!(commits.len(), 1);
+        assert_eq!(commits[0].subject, "Initial commit");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_blame() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = repo().await?;
+
+        let lines = blame(dir.path(), Path::new("lib.rs"), 1, 2).await?;
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].line, 1);
+        assert_eq!(lines[0].summary, "Initial commit");
+        assert_eq!(lines[0].content, "fn a() {}");
+        assert_eq!(lines[1].line, 2);
+        assert_eq!(lines[1].summary, "Return a value from b");
+        assert_eq!(lines[1].author, "Jane Doe");
+        assert!(lines[1].author_time > 0);
+
+        assert!(blame(dir.path(), Path::new("lib.rs"), 5, 6).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_diff_includes_uncommitted_changes() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = repo().await?;
+        run(dir.path(), &["checkout", "-q", "-b", "fix"]).await?;
+        fs::write(dir.path().join("README.md"), "# Readme\nMore\n")?;
+        commit(dir.path(), "Extend readme").await?;
+        fs::write(dir.path().join("lib.rs"), "fn a() {}\n")?;
+
+        let diff = diff(dir.path(), "main", None).await?;
+        assert!(!diff.truncated);
+        assert!(diff.diff.contains("+More"));
+        assert!(diff.diff.contains("-fn b() -> u8 { 1 }"));
+
+        let only_lib = super::diff(dir.path(), "main", Some(Path::new("lib.rs"))).await?;
+        assert!(!only_lib.diff.contains("README.md"));
+
+        assert!(super::diff(dir.path(), "missing-branch", None)
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_diff_includes_untracked_files() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = repo().await?;
+        fs::create_dir_all(dir.path().join("src"))?;
+        fs::write(dir.path().join("src/new.rs"), "fn new() {}\n")?;
+        fs::write(dir.path().join(".gitignore"), "target\n")?;
+        fs::create_dir_all(dir.path().join("target"))?;
+        fs::write(dir.path().join("target/build.log"), "output\n")?;
+
+        let diff = diff(dir.path(), "main", None).await?;
+        assert!(diff.diff.contains("+++ b/src/new.rs"));
+        assert!(diff.diff.contains("+fn new() {}"));
+        assert!(!diff.diff.contains("build.log"));
+
+        let only_lib = super::diff(dir.path(), "main", Some(Path::new("lib.rs"))).await?;
+        assert!(!only_lib.diff.contains("new.rs"));
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+use async_trait::async_trait;
+use log::info;
+use octocrab::{params, Octocrab};
+
+use crate::errors::CoderError;
+use crate::scm::{Issue, IssueFilter, PullRequest, ScmProvider};
+
+/// GitHub provider backed by Octocrab
+pub struct GitHub {
+    octocrab: Octocrab,
+    owner: String,
+    repo: String,
+}
+
+impl GitHub {
+    /// Create a new GitHub provider
+    ///
+    /// # Arguments
+    ///
+    /// * `base_url` - Optional API URL for GitHub Enterprise (e.g. https://github.example.com/api/v3)
+    /// * `token` - Personal access token
+    /// * `owner` - Owner of the repository
+    /// * `repo` - Name of the repository
+    pub fn new(
+        base_url: Option<&str>,
+        token: &str,
+        owner: &str,
+        repo: &str,
+    ) -> Result<Self, CoderError> {
+        let mut builder = Octocrab::builder().personal_token(token.to_string());
+        if let Some(url) = base_url {
+            builder = builder.base_uri(url)?;
+        }
+
+        Ok(Self {
+            octocrab: builder.build()?,
+            owner: owner.to_string(),
+            repo: repo.to_string(),
+        })
+    }
+}
+
+impl From<octocrab::models::issues::Issue> for Issue {
+    fn from(issue: octocrab::models::issues::Issue) -> Self {
+        Self {
+            number: issue.number,
+            title: issue.title,
+            body: issue.body,
+        }
+    }
+}
+
+impl From<octocrab::models::pulls::PullRequest> for PullRequest {
+    fn from(pr: octocrab::models::pulls::PullRequest) -> Self {
+        Self {
+            number: pr.number,
+            title: pr.title,
+            body: pr.body,
+            url: pr.html_url.map(|url| url.to_string()),
+        }
+    }
+}
+
+#[async_trait]
+impl ScmProvider for GitHub {
+    async fn current_user(&self) -> Result<String, CoderError> {
+        Ok(self.octocrab.current().user().await?.login)
+    }
+
+    async fn get_issue(&self, number: u64) -> Result<Issue, CoderError> {
+        info!("Pulling issue #{} from GitHub", number);
+
+        let issue = self
+            .octocrab
+            .issues(&self.owner, &self.repo)
+            .get(number)
+            .await?;
+
+        Ok(issue.into())
+    }
+
+    async fn list_issues(&self, filter: &IssueFilter) -> Result<Vec<Issue>, CoderError> {
+        let handler = self.octocrab.issues(&self.owner, &self.repo);
+        let mut request = handler.list().state(params::State::Open).per_page(100);
+        if let Some(assignee) = &filter.assignee {
+            request = request.assignee(assignee.as_str());
+        }
+        if !filter.labels.is_empty() {
+            request = request.labels(&filter.labels);
+        }
+
+        let page = request.send().await?;
+
+        // The issues endpoint also returns pull requests
+        Ok(page
+            .items
+            .into_iter()
+            .filter(|issue| issue.pull_request.is_none())
+            .map(Issue::from)
+            .collect())
+    }
+
+    async fn create_pull_request(
+        &self,
+        head: &str,
+        base: &str,
+        title: &str,
+        body: &str,
+    ) -> Result<PullRequest, CoderError> {
+        let pr = self
+            .octocrab
+            .pulls(&self.owner, &self.repo)
+            .create(title, head, base)
+            .body(body)
+            .send()
+            .await?;
+
+        Ok(pr.into())
+    }
+
+    async fn find_pull_request(&self, head: &str) -> Result<Option<PullRequest>, CoderError> {
+        // GitHub filters by owner:branch, a plain branch lives in the repository itself
+        let head = if head.contains(':') {
+            head.to_string()
+        } else {
+            format!("{}:{}", self.owner, head)
+        };
+        let page = self
+            .octocrab
+            .pulls(&self.owner, &self.repo)
+            .list()
+            .state(params::State::Open)
+            .head(head)
+            .per_page(1)
+            .send()
+            .await?;
+
+        Ok(page.items.into_iter().next().map(PullRequest::from))
+    }
+
+    async fn update_pull_request(
+        &self,
+        number: u64,
+        title: Option<&str>,
+        body: Option<&str>,
+    ) -> Result<PullRequest, CoderError> {
+        let pr = self
+            .octocrab
+            .pulls(&self.owner, &self.repo)
+            .update(number)
+            .title::<&str>(title)
+            .body::<&str>(body)
+            .send()
+            .await?;
+
+        Ok(pr.into())
+    }
+
+    async fn comment(&self, issue: u64, body: &str) -> Result<(), CoderError> {
+        self.octocrab
+            .issues(&self.owner, &self.repo)
+            .create_comment(issue, body)
+            .await?;
+        Ok(())
+    }
+
+    async fn add_labels(&self, issue: u64, labels: &[String]) -> Result<(), CoderError> {
+        self.octocrab
+            .issues(&self.owner, &self.repo)
+            .add_labels(issue, labels)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,552 @@
+use async_trait::async_trait;
+use log::info;
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::CoderError;
+use crate::scm::{Issue, IssueFilter, PullRequest, ScmProvider};
+
+pub const DEFAULT_URL: &str = "https://gitlab.com";
+
+/// Minimal GitLab REST (v4) client scoped to a single project
+pub struct GitLab {
+    client: Client,
+    base_url: String,
+    token: String,
+    project: String,
+    repo: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitLabProject {
+    id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitLabUser {
+    username: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitLabIssue {
+    iid: u64,
+    title: String,
+    description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GitLabMergeRequest {
+    iid: u64,
+    title: String,
+    description: Option<String>,
+    web_url: Option<String>,
+    #[serde(default)]
+    source_project_id: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct CreateMergeRequest<'a> {
+    source_branch: &'a str,
+    target_branch: &'a str,
+    title: &'a str,
+    description: &'a str,
+    /// Set when the source branch is in a fork
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target_project_id: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct UpdateMergeRequest<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'a str>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    message: Option<serde_json::Value>,
+    error: Option<String>,
+}
+
+impl From<GitLabIssue> for Issue {
+    fn from(issue: GitLabIssue) -> Self {
+        Self {
+            number: issue.iid,
+            title: issue.title,
+            body: issue.description,
+        }
+    }
+}
+
+impl From<GitLabMergeRequest> for PullRequest {
+    fn from(mr: GitLabMergeRequest) -> Self {
+        Self {
+            number: mr.iid,
+            title: Some(mr.title),
+            body: mr.description,
+            url: mr.web_url,
+        }
+    }
+}
+
+impl GitLab {
+    /// Create a new GitLab client
+    ///
+    /// # Arguments
+    ///
+    /// * `base_url` - Base URL of the GitLab instance (e.g. https://gitlab.com)
+    /// * `token` - Personal or project access token
+    /// * `owner` - Namespace of the project, may contain subgroups (e.g. group/subgroup)
+    /// * `repo` - Name of the project
+    pub fn new(base_url: &str, token: &str, owner: &str, repo: &str) -> Self {
+        Self {
+            client: Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+            project: project_id(owner, repo),
+            repo: repo.to_string(),
+        }
+    }
+
+    fn project_url(&self) -> String {
+        format!("{}/api/v4/projects/{}", self.base_url, self.project)
+    }
+
+    fn authorized(&self, request: RequestBuilder) -> RequestBuilder {
+        request.header("PRIVATE-TOKEN", &self.token)
+    }
+}
+
+#[async_trait]
+impl ScmProvider for GitLab {
+    async fn current_user(&self) -> Result<String, CoderError> {
+        let url = format!("{}/api/v4/user", self.base_url);
+        let response = self.authorized(self.client.get(&url)).send().await?;
+
+        Ok(parse_response::<GitLabUser>(response).await?.username)
+    }
+
+    async fn get_issue(&self, number: u64) -> Result<Issue, CoderError> {
+        info!("Pulling issue #{} from GitLab", number);
+
+        let url = format!("{}/issues/{}", self.project_url(), number);
+        let response = self.authorized(self.client.get(&url)).send().await?;
+
+        Ok(parse_response::<GitLabIssue>(response).await?.into())
+    }
+
+    async fn list_issues(&self, filter: &IssueFilter) -> Result<Vec<Issue>, CoderError> {
+        let url = format!("{}/issues", self.project_url());
+        let mut query = vec![
+            ("state", "opened".to_string()),
+            ("per_page", "100".to_string()),
+        ];
+        if let Some(assignee) = &filter.assignee {
+            query.push(("assignee_username", assignee.clone()));
+        }
+        if !filter.labels.is_empty() {
+            query.push(("labels", filter.labels.join(",")));
+        }
+
+        let response = self
+            .authorized(self.client.get(&url))
+            .query(&query)
+            .send()
+            .await?;
+
+        let issues: Vec<GitLabIssue> = parse_response(response).await?;
+        Ok(issues.into_iter().map(Issue::from).collect())
+    }
+
+    async fn create_pull_request(
+        &self,
+        head: &str,
+        base: &str,
+        title: &str,
+        body: &str,
+    ) -> Result<PullRequest, CoderError> {
+        info!("Creating MR from {} into {} on GitLab", head, base);
+
+        // A head of owner:branch is a branch of the fork, the MR is opened there
+        let (url, source_branch, target_project_id) = match head.split_once(':') {
+            Some((owner, branch)) => {
+                let response = self
+                    .authorized(self.client.get(self.project_url()))
+                    .send()
+                    .await?;
+                let target: GitLabProject = parse_response(response).await?;
+                let url = format!(
+                    "{}/api/v4/projects/{}/merge_requests",
+                    self.base_url,
+                    project_id(owner, &self.repo)
+                );
+                (url, branch, Some(target.id))
+            }
+            None => (format!("{}/merge_requests", self.project_url()), head, None),
+        };
+        let payload = CreateMergeRequest {
+            source_branch,
+            target_branch: base,
+            title,
+            description: body,
+            target_project_id,
+        };
+        let response = self
+            .authorized(self.client.post(&url))
+            .json(&payload)
+            .send()
+            .await?;
+
+        Ok(parse_response::<GitLabMergeRequest>(response).await?.into())
+    }
+
+    async fn find_pull_request(&self, head: &str) -> Result<Option<PullRequest>, CoderError> {
+        // MRs from a fork are listed in the target project, the fork tells them apart
+        let (source_branch, source_project) = match head.split_once(':') {
+            Some((owner, branch)) => {
+                let url = format!(
+                    "{}/api/v4/projects/{}",
+                    self.base_url,
+                    project_id(owner, &self.repo)
+                );
+                let response = self.authorized(self.client.get(&url)).send().await?;
+                let fork: GitLabProject = parse_response(response).await?;
+                (branch, Some(fork.id))
+            }
+            None => (head, None),
+        };
+
+        let url = format!("{}/merge_requests", self.project_url());
+        let response = self
+            .authorized(self.client.get(&url))
+            .query(&[("state", "opened"), ("source_branch", source_branch)])
+            .send()
+            .await?;
+        let merge_requests: Vec<GitLabMergeRequest> = parse_response(response).await?;
+
+        Ok(merge_requests
+            .into_iter()
+            .find(|mr| source_project.is_none() || mr.source_project_id == source_project)
+            .map(PullRequest::from))
+    }
+
+    async fn update_pull_request(
+        &self,
+        number: u64,
+        title: Option<&str>,
+        body: Option<&str>,
+    ) -> Result<PullRequest, CoderError> {
+        let url = format!("{}/merge_requests/{}", self.project_url(), number);
+        let payload = UpdateMergeRequest {
+            title,
+            description: body,
+        };
+        let response = self
+            .authorized(self.client.put(&url))
+            .json(&payload)
+            .send()
+            .await?;
+
+        Ok(parse_response::<GitLabMergeRequest>(response).await?.into())
+    }
+
+    async fn comment(&self, issue: u64, body: &str) -> Result<(), CoderError> {
+        let url = format!("{}/issues/{}/notes", self.project_url(), issue);
+        let response = self
+            .authorized(self.client.post(&url))
+            .json(&serde_json::json!({ "body": body }))
+            .send()
+            .await?;
+
+        parse_response::<serde_json::Value>(response).await?;
+        Ok(())
+    }
+
+    async fn add_labels(&self, issue: u64, labels: &[String]) -> Result<(), CoderError> {
+        let url = format!("{}/issues/{}", self.project_url(), issue);
+        let response = self
+            .authorized(self.client.put(&url))
+            .json(&serde_json::json!({ "add_labels": labels.join(",") }))
+            .send()
+            .await?;
+
+        parse_response::<serde_json::Value>(response).await?;
+        Ok(())
+    }
+}
+
+/// GitLab addresses projects by their URL-encoded full path (e.g. group%2Fsubgroup%2Frepo)
+fn project_id(owner: &str, repo: &str) -> String {
+    let path = if owner.is_empty() {
+        repo.to_string()
+    } else {
+        format!("{}/{}", owner.trim_matches('/'), repo)
+    };
+    path.replace('/', "%2F")
+}
+
+async fn parse_response<T: for<'de> Deserialize<'de>>(
+    response: reqwest::Response,
+) -> Result<T, CoderError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response.json().await?);
+    }
+
+    let body = response.text().await.unwrap_or_default();
+    let message = match serde_json::from_str::<ErrorResponse>(&body) {
+        Ok(ErrorResponse {
+            message: Some(message),
+            ..
+        }) => match message {
+            serde_json::Value::String(s) => s,
+            other => other.to_string(),
+        },
+        Ok(ErrorResponse {
+            error: Some(error), ..
+        }) => error,
+        _ => body,
+    };
+
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(CoderError::GitLabError(format!(
+            "Unauthorized ({}): {}",
+            status, message
+        ))),
+        _ => Err(CoderError::GitLabError(format!("{}: {}", status, message))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Matcher;
+
+    #[test]
+    fn test_project_id_encodes_namespace() {
+        assert_eq!(project_id("owner", "repo"), "owner%2Frepo");
+        assert_eq!(
+            project_id("group/subgroup", "repo"),
+            "group%2Fsubgroup%2Frepo"
+        );
+        assert_eq!(project_id("", "repo"), "repo");
+    }
+
+    #[tokio::test]
+    async fn test_get_issue() -> Result<(), Box<dyn std::error::Error>> {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/api/v4/projects/group%2Fsubgroup%2Frepo/issues/7")
+            .match_header("PRIVATE-TOKEN", "secret")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r###"{"iid":7,"title":"Broken build","description":"## Description\nIt fails"}"###,
+            )
+            .create_async()
+            .await;
+
+        let gitlab = GitLab::new(&server.url(), "secret", "group/subgroup", "repo");
+        let issue = gitlab.get_issue(7).await?;
+
+        mock.assert_async().await;
+        assert_eq!(issue.number, 7);
+        assert_eq!(issue.title, "Broken build");
+        assert_eq!(issue.body, Some("## Description\nIt fails".to_string()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_issue_not_found() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/api/v4/projects/owner%2Frepo/issues/404")
+            .with_status(404)
+            .with_body(r#"{"message":"404 Not found"}"#)
+            .create_async()
+            .await;
+
+        let gitlab = GitLab::new(&server.url(), "secret", "owner", "repo");
+        let err = gitlab.get_issue(404).await.unwrap_err();
+
+        assert!(err.to_string().contains("404 Not found"));
+    }
+
+    #[tokio::test]
+    async fn test_create_merge_request() -> Result<(), Box<dyn std::error::Error>> {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/v4/projects/owner%2Frepo/merge_requests")
+            .match_header("PRIVATE-TOKEN", "secret")
+            .match_body(Matcher::Json(serde_json::json!({
+                "source_branch": "fix-1",
+                "target_branch": "main",
+                "title": "Fix the build",
+                "description": "Closes #1"
+            })))
+            .with_status(201)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"iid":3,"title":"Fix the build","description":"Closes #1","web_url":"https://gitlab.example.com/owner/repo/-/merge_requests/3"}"#,
+            )
+            .create_async()
+            .await;
+
+        let gitlab = GitLab::new(&format!("{}/", server.url()), "secret", "owner", "repo");
+        let mr = gitlab
+            .create_pull_request("fix-1", "main", "Fix the build", "Closes #1")
+            .await?;
+
+        mock.assert_async().await;
+        assert_eq!(mr.number, 3);
+        assert_eq!(
+            mr.url,
+            Some("https://gitlab.example.com/owner/repo/-/merge_requests/3".to_string())
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_merge_request_from_fork() -> Result<(), Box<dyn std::error::Error>> {
+        let mut server = mockito::Server::new_async().await;
+        let project = server
+            .mock("GET", "/api/v4/projects/owner%2Frepo")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"id":42}"#)
+            .create_async()
+            .await;
+        let mock = server
+            .mock("POST", "/api/v4/projects/coder-bot%2Frepo/merge_requests")
+            .match_body(Matcher::Json(serde_json::json!({
+                "source_branch": "fix-1",
+                "target_branch": "develop",
+                "title": "Fix the build",
+                "description": "Closes #1",
+                "target_project_id": 42
+            })))
+            .with_status(201)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"iid":4,"title":"Fix the build","description":"Closes #1"}"#)
+            .create_async()
+            .await;
+
+        let gitlab = GitLab::new(&server.url(), "secret", "owner", "repo");
+        let mr = gitlab
+            .create_pull_request("coder-bot:fix-1", "develop", "Fix the build", "Closes #1")
+            .await?;
+
+        project.assert_async().await;
+        mock.assert_async().await;
+        assert_eq!(mr.number, 4);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_find_merge_request_from_fork() -> Result<(), Box<dyn std::error::Error>> {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/api/v4/projects/coder-bot%2Frepo")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"id":7}"#)
+            .create_async()
+            .await;
+        let mock = server
+            .mock("GET", "/api/v4/projects/owner%2Frepo/merge_requests")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("state".into(), "opened".into()),
+                Matcher::UrlEncoded("source_branch".into(), "coder/issue-1".into()),
+            ]))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"[{"iid":2,"title":"Same branch upstream","description":null,"source_project_id":42},
+                    {"iid":5,"title":"Fix the build","description":null,"source_project_id":7}]"#,
+            )
+            .expect(2)
+            .create_async()
+            .await;
+
+        let gitlab = GitLab::new(&server.url(), "secret", "owner", "repo");
+        let mr = gitlab.find_pull_request("coder-bot:coder/issue-1").await?;
+        assert_eq!(mr.map(|mr| mr.number), Some(5));
+        let mr = gitlab.find_pull_request("coder/issue-1").await?;
+        assert_eq!(mr.map(|mr| mr.number), Some(2));
+
+        mock.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_issues_with_filter() -> Result<(), Box<dyn std::error::Error>> {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/api/v4/projects/owner%2Frepo/issues")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("state".into(), "opened".into()),
+                Matcher::UrlEncoded("assignee_username".into(), "coder-bot".into()),
+                Matcher::UrlEncoded("labels".into(), "bug,coder".into()),
+            ]))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"[{"iid":1,"title":"First","description":null},{"iid":2,"title":"Second","description":"body"}]"#)
+            .create_async()
+            .await;
+
+        let gitlab = GitLab::new(&server.url(), "secret", "owner", "repo");
+        let issues = gitlab
+            .list_issues(&IssueFilter {
+                assignee: Some("coder-bot".to_string()),
+                labels: vec!["bug".to_string(), "coder".to_string()],
+            })
+            .await?;
+
+        mock.assert_async().await;
+        assert_eq!(
+            issues.iter().map(|i| i.number).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_current_user() -> Result<(), Box<dyn std::error::Error>> {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/api/v4/user")
+            .match_header("PRIVATE-TOKEN", "secret")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"id":42,"username":"coder-bot"}"#)
+            .create_async()
+            .await;
+
+        let gitlab = GitLab::new(&server.url(), "secret", "owner", "repo");
+        assert_eq!(gitlab.current_user().await?, "coder-bot");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_update_merge_request() -> Result<(), Box<dyn std::error::Error>> {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("PUT", "/api/v4/projects/owner%2Frepo/merge_requests/3")
+            .match_body(Matcher::Json(serde_json::json!({ "title": "New title" })))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"iid":3,"title":"New title","description":"Closes #1"}"#)
+            .create_async()
+            .await;
+
+        let gitlab = GitLab::new(&server.url(), "secret", "owner", "repo");
+        let mr = gitlab
+            .update_pull_request(3, Some("New title"), None)
+            .await?;
+
+        mock.assert_async().await;
+        assert_eq!(mr.title, Some("New title".to_string()));
+        Ok(())
+    }
+}
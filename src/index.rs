@@ -1,14 +1,117 @@
+use globset::GlobMatcher;
 use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
 use std::{fs, io, path::Path};
 
-pub fn build_tree() -> io::Result<String> {
+/// Maximum number of entries returned by `list`
+pub const MAX_LIST_ENTRIES: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryType {
+    File,
+    Dir,
+    Symlink,
+}
+
+/// A file or directory found by `list`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    /// Path relative to the repository root
+    pub path: String,
+    pub r#type: EntryType,
+    /// Size in bytes, only set for files
+    pub size: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Listing {
+    pub entries: Vec<Entry>,
+    /// Whether the listing stopped at `MAX_LIST_ENTRIES`
+    pub truncated: bool,
+}
+
+/// Walk of the repository skipping hidden and gitignored files
+pub fn walk_builder(root: &Path) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(root);
+    builder.hidden(true).git_ignore(true);
+    builder
+}
+
+/// List the entries under `path`, skipping hidden and gitignored files
+///
+/// # Arguments
+///
+/// * `root` - Repository root, reported paths are relative to it
+/// * `path` - File or directory to list, relative to the root
+/// * `depth` - How many directory levels to descend, 1 lists the direct children
+/// * `glob` - Only include entries whose name or relative path matches
+///
+/// # Returns
+///
+/// * `io::Result<Listing>` - Entries sorted by path, capped at `MAX_LIST_ENTRIES`
+pub fn list(
+    root: &Path,
+    path: &Path,
+    depth: usize,
+    glob: Option<&GlobMatcher>,
+) -> io::Result<Listing> {
+    let start = root.join(path);
+    let mut listing = Listing {
+        entries: Vec::new(),
+        truncated: false,
+    };
+
+    let walker = walk_builder(&start)
+        .max_depth(Some(depth))
+        .sort_by_file_path(|a, b| a.cmp(b))
+        .build();
+    for entry in walker {
+        let entry = entry.map_err(io::Error::other)?;
+        // The starting directory itself is not part of its listing
+        if entry.depth() == 0 && entry.path().is_dir() {
+            continue;
+        }
+
+        let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        if let Some(glob) = glob {
+            if !glob.is_match(relative) && !glob.is_match(entry.file_name()) {
+                continue;
+            }
+        }
+
+        if listing.entries.len() == MAX_LIST_ENTRIES {
+            listing.truncated = true;
+            break;
+        }
+
+        let file_type = entry.file_type();
+        let (r#type, size) = match file_type {
+            Some(t) if t.is_symlink() => (EntryType::Symlink, None),
+            Some(t) if t.is_dir() => (EntryType::Dir, None),
+            _ => (
+                EntryType::File,
+                Some(entry.metadata().map_err(io::Error::other)?.len()),
+            ),
+        };
+        listing.entries.push(Entry {
+            path: relative.to_string_lossy().to_string(),
+            r#type,
+            size,
+        });
+    }
+
+    Ok(listing)
+}
+
+pub fn build_tree(root: &Path) -> io::Result<String> {
     let mut tree = String::from(".\n");
     let mut previous_depth = 0;
 
-    let walker = WalkBuilder::new(".").hidden(true).git_ignore(true).build();
+    let walker = walk_builder(root).build();
 
     for entry in walker.flatten() {
-        if entry.path() == Path::new(".") {
+        if entry.path() == root {
             continue;
         }
 
@@ -40,7 +143,7 @@ pub fn build_tree() -> io::Result<String> {
 
 pub fn build_content() -> io::Result<String> {
     let mut content = String::from("content:\n");
-    let walker = WalkBuilder::new(".").hidden(true).git_ignore(true).build();
+    let walker = walk_builder(Path::new(".")).build();
 
     for entry in walker.flatten() {
         if entry.path() == Path::new(".") || !entry.path().is_file() {
@@ -62,3 +165,58 @@ pub fn build_content() -> io::Result<String> {
 
     Ok(content)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use globset::Glob;
+    use tempfile::tempdir;
+
+    fn setup() -> io::Result<tempfile::TempDir> {
+        let dir = tempdir()?;
+        fs::create_dir_all(dir.path().join(".git"))?;
+        fs::create_dir_all(dir.path().join("src/tools"))?;
+        fs::create_dir_all(dir.path().join("target"))?;
+        fs::write(dir.path().join(".gitignore"), "target/\n")?;
+        fs::write(dir.path().join("src/main.rs"), "fn main() {}\n")?;
+        fs::write(dir.path().join("src/tools/read.rs"), "")?;
+        fs::write(dir.path().join("README.md"), "# Test\n")?;
+        Ok(dir)
+    }
+
+    #[test]
+    fn test_list_direct_children() -> io::Result<()> {
+        let dir = setup()?;
+        let listing = list(dir.path(), Path::new(""), 1, None)?;
+
+        let entries: Vec<(&str, EntryType, Option<u64>)> = listing
+            .entries
+            .iter()
+            .map(|e| (e.path.as_str(), e.r#type, e.size))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("README.md", EntryType::File, Some(7)),
+                ("src", EntryType::Dir, None),
+            ]
+        );
+        assert!(!listing.truncated);
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_with_depth_and_glob() -> io::Result<()> {
+        let dir = setup()?;
+        let glob = Glob::new("*.rs").unwrap().compile_matcher();
+        let listing = list(dir.path(), Path::new("src"), 5, Some(&glob))?;
+
+        let paths: Vec<&str> = listing.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["src/main.rs", "src/tools/read.rs"]);
+
+        let listing = list(dir.path(), Path::new("src/main.rs"), 1, None)?;
+        assert_eq!(listing.entries.len(), 1);
+        assert_eq!(listing.entries[0].size, Some(13));
+        Ok(())
+    }
+}
@@ -1,33 +1,37 @@
 use crate::cli::{Cli, Commands};
 use crate::errors::CoderError;
+use agent::{Agent, AgentStatus};
 #[cfg(feature = "completions")]
 use clap::CommandFactory;
 use clap::Parser;
 #[cfg(feature = "completions")]
 use clap_complete::generate;
-use conversation::Conversation;
-use inference_gateway_sdk::{
-    InferenceGatewayAPI, InferenceGatewayClient, Message, MessageRole, Provider,
-};
 use log::{debug, info, warn};
-use std::{env, fs, panic, path::Path, str::FromStr, thread::sleep, time::Duration};
+use scm::ScmProvider;
+use std::{env, fs, path::Path, time::Duration};
 
+mod agent;
+mod auto;
 mod cli;
 mod config;
 mod conversation;
+mod diagnostics;
+mod docs;
+mod edit;
 mod errors;
+mod git;
+mod github;
+mod gitlab;
 mod index;
+mod process;
+mod sandbox;
+mod scm;
+mod scope;
+mod search;
 mod tools;
 mod utils;
-
-fn setup_panic_handler(conversation: Conversation) {
-    let default_hook = panic::take_hook();
-    panic::set_hook(Box::new(move |panic_info| {
-        info!("{:?}", conversation);
-
-        default_hook(panic_info);
-    }));
-}
+mod workspace;
+mod worktree;
 
 fn init() -> Result<(), CoderError> {
     info!("Initializing AI Coder agent...");
@@ -54,14 +58,124 @@ fn init() -> Result<(), CoderError> {
     Ok(())
 }
 
+/// Run the agent against a single issue until it calls `done`
+async fn fix_issue(
+    config: &config::Config,
+    scm: &dyn ScmProvider,
+    issue: u64,
+) -> Result<AgentStatus, CoderError> {
+    let repository = env::current_dir()?;
+    let branch = tools::issue_branch(issue);
+    // A previous run's pull request is continued, its commits are the starting point
+    let existing = scm.find_pull_request(&config.scm.head(&branch)).await?;
+    let (start_point, pull_request) = match &existing {
+        Some(pr) => {
+            info!(
+                "Continuing pull request #{} for issue #{}",
+                pr.number, issue
+            );
+            (
+                git::fetch_branch(&repository, config.scm.push_remote(), &branch).await?,
+                format!(
+                    "Pull request #{} is already open for this issue, the workspace contains its commits. Build on them, pull_request pushes your commits to it and updates its title and body.",
+                    pr.number
+                ),
+            )
+        }
+        None => (
+            "HEAD".to_string(),
+            "No pull request is open for this issue yet.".to_string(),
+        ),
+    };
+    let worktree =
+        worktree::Worktree::create(&repository, &uuid::Uuid::new_v4().to_string(), &start_point)
+            .await?;
+    let status = fix_in_worktree(config, scm, issue, &worktree, &pull_request).await;
+    worktree.remove().await;
+    status
+}
+
+/// Run the agent for the issue in the worktree prepared by `fix_issue`
+async fn fix_in_worktree(
+    config: &config::Config,
+    scm: &dyn ScmProvider,
+    issue: u64,
+    worktree: &worktree::Worktree,
+    pull_request: &str,
+) -> Result<AgentStatus, CoderError> {
+    let mut agent = Agent::new(config, scm, tools::get_tools())?.with_worktree(worktree);
+
+    let system_prompt = format!(
+        r#"You are a senior software engineer specializing in {language} development working to fix an issue reported in {scm}.
+
+When you need to call a tool, don't answer in XML.
+
+WORKSPACE:
+{tree}
+
+CHANGES SCOPE:
+{scope}
+
+PULL REQUEST:
+{pull_request}
+
+PROCESS:
+1. Validate issue #{issue} [tool: issue_validate]
+2. Pull issue details [tool: issue_pull] 
+   When the issue mentions a regression, check the recent history [tool: git_log, git_blame, git_diff]
+3. Locate and analyze the relevant code and documentation [tool: code_list, code_search, code_read, docs_reference]
+4. Implement a fix [tool: code_edit, code_write for new files, code_delete, code_move]
+5. Validate changes, only if you made changes to the code:
+    - Lint code [tool: code_lint]
+    - Run analysis [tool: code_analyse]
+    - Run tests [tool: code_test], use a filter for the affected tests before running the whole suite
+6. Commit each logical change with a conventional commit message [tool: git_commit]
+7. Create or update the {scm} pull request [pull_request]
+8. Call "done" when complete [done]
+
+When you execute a tool, the following output would be provided in JSON format:
+
+```json
+{{
+  "status": "ok",
+  "message": "tool_message",
+  "result": "tool_result"
+  "retry": false
+}}
+```
+
+When retry is set to true, that means you need to re-run the tool with the provided arguments, ensure you send valid JSON.
+When the status is "failed", the command ran but reported problems, use the exit code, diagnostics and output in the result to fix them.
+When the tool result is empty, it means the tool did not find any issues.
+If it's not empty, review the issue and fix it accordingly.
+
+Focus on producing working solutions with minimal discussion. Do not ask questions. Provide a complete solution."#,
+        language = config.language.name,
+        scm = config.scm.name,
+        tree = index::build_tree(worktree.path())?,
+        scope = scope::Scope::new(&config.agent.scope)?.describe(),
+        pull_request = pull_request,
+        issue = issue,
+    );
+
+    agent.system(system_prompt);
+    agent.user(format!(
+        "Please fix the issue #{} - first pull it before jumping to conclusions. Just fix it, do not explain.",
+        issue
+    ));
+
+    agent.run().await
+}
+
 #[tokio::main]
 async fn main() -> Result<(), CoderError> {
     if env::var("RUST_LOG").is_err() {
         env::set_var("RUST_LOG", "info");
     }
     env_logger::init();
+    agent::setup_panic_handler();
+    process::kill_running_on_shutdown();
 
-    let tools = tools::get_tools();
     let cli = Cli::parse();
 
     if let Commands::Init {} = cli.command {
@@ -81,9 +195,6 @@ async fn main() -> Result<(), CoderError> {
 
     debug!("Config: {:#?}", config);
 
-    let model = &config.agent.model;
-    let provider = Provider::try_from(config.agent.provider.as_str())?;
-
     match cli.command {
         #[cfg(feature = "completions")]
         Commands::Completions { shell } => {
@@ -98,7 +209,7 @@ async fn main() -> Result<(), CoderError> {
             let coder_dir = Path::new(".coder");
             fs::create_dir_all(coder_dir)?;
 
-            let tree = index::build_tree()?;
+            let tree = index::build_tree(Path::new("."))?;
             let content = index::build_content()?;
 
             let index_content = format!(
@@ -113,280 +224,146 @@ async fn main() -> Result<(), CoderError> {
             fs::write(coder_dir.join("index.yaml"), index_content)?;
             info!("Created index at .coder/index.yaml");
         }
-        Commands::Auto {} => {
-            info!("Starting autonomous mode...");
-
-            // let issue_fetcher = IssueFetcher::new(config.clone())?;
-            // let client = InferenceGatewayClient::new(&config.api.endpoint)
-            //     .with_max_tokens(Some(900))
-            //     .with_tools(Some(tools));
-
-            // let mut processed_count = 0;
-
-            // loop {
-            //     info!("Checking for assigned issues...");
-
-            //     // Parse label string into vector if provided
-            //     let label_vec = labels.as_ref().map(|l| {
-            //         l.split(',')
-            //             .map(|s| s.trim().to_string())
-            //             .collect::<Vec<String>>()
-            //     });
-
-            //     // Fetch assigned issues
-            //     let mut issues = issue_fetcher.fetch_assigned_issues(label_vec).await?;
-
-            //     if issues.is_empty() {
-            //         info!("No issues assigned. Waiting for next check...");
-            //     } else {
-            //         info!("Found {} assigned issues", issues.len());
-
-            //         // Process issues one by one
-            //         while let Some(issue_number) = issues.pop_front() {
-            //             info!("Processing issue #{}", issue_number);
-
-            //             // Update project status to "In Progress"
-            //             tools::update_project_status(
-            //                 &config.scm.owner,
-            //                 &config.scm.repository,
-            //                 issue_number,
-            //                 "In Progress",
-            //             )
-            //             .await?;
-
-            //             // Process the issue (reusing fix command logic)
-            //             let convo = process_issue(&client, &config, issue_number).await?;
-
-            //             // Update project status to "Done"
-            //             update_project_status(
-            //                 &config.scm.owner,
-            //                 &config.scm.repository,
-            //                 issue_number,
-            //                 "Done",
-            //             )
-            //             .await?;
-
-            //             processed_count += 1;
-
-            //             // Check if we've hit the limit
-            //             if limit > 0 && processed_count >= limit {
-            //                 info!("Reached processing limit of {} issues", limit);
-            //                 return Ok(());
-            //             }
-            //         }
-            //     }
-
-            //     info!("Waiting {} seconds before next check...", interval);
-            //     tokio::time::sleep(tokio::time::Duration::from_secs(interval)).await;
-            // }
-        }
-        Commands::Fix {
-            issue,
-            further_instruction,
+        Commands::Auto {
+            interval,
+            limit,
+            labels,
         } => {
-            info!("Fixing issue #{}...", issue);
-            info!("Further instructions: {:?}", further_instruction);
-
-            let client = InferenceGatewayClient::new(&config.api.endpoint)
-                .with_max_tokens(Some(900))
-                .with_tools(Some(tools));
-
-            let mut convo = Conversation::new(model.to_string(), provider, config.agent.max_tokens);
-
-            setup_panic_handler(convo.clone());
-
-            let system_prompt = format!(
-                r#"You are a senior software engineer specializing in {language} development working to fix an issue reported in {scm}.
-
-When you need to call a tool, don't answer in XML.
-
-WORKSPACE:
-{tree}
-
-CHANGES SCOPE:
-The changes you are allowed to make are limited to playground/bug1 directory.
-
-PROCESS:
-1. Validate issue #{issue} [tool: issue_validate]
-2. Pull issue details [tool: issue_pull] 
-3. Analyze code and documentation [tool: code_read]
-4. Implement a fix [tool: code_write]
-5. Validate changes, only if you made changes to the code:
-    - Lint code [tool: code_lint]
-    - Run analysis [tool: code_analyse]
-    - Run tests [tool: code_test]
-6. Create {scm} pull request [pull_request]
-7. Call "done" when complete [done]
-
-When you execute a tool, the following output would be provided in JSON format:
-
-```json
-{{
-  "status": "ok",
-  "message": "tool_message",
-  "result": "tool_result"
-  "retry": false
-}}
-```
-
-When retry is set to true, that means you need to re-run the tool with the provided arguments, ensure you send valid JSON.
-When the tool result is empty, it means the tool did not find any issues.
-If it's not empty, review the issue and fix it accordingly.
-
-Focus on producing working solutions with minimal discussion. Do not ask questions. Provide a complete solution."#,
-                language = config.language.name,
-                scm = config.scm.name,
-                tree = index::build_tree()?,
-                issue = issue,
-            );
-
-            convo.add_message(Message {
-                role: MessageRole::System,
-                content: system_prompt,
-                ..Default::default()
-            });
-
-            convo.add_message(Message {
-                role: MessageRole::User,
-                content: format!("Please fix the issue #{} - first pull it before jumping to conclusions. Just fix it, do not explain.", issue),
-                ..Default::default()
-            });
-
-            let timeout = Duration::from_secs(300);
-            info!("Starting AI Coder agent...");
-            info!("Press Ctrl+C to stop the agent.");
-            'outer: loop {
-                if timeout.as_secs() == 0 {
-                    warn!("Timeout reached. Exiting...");
-                    break;
-                }
-
-                let resp: inference_gateway_sdk::CreateChatCompletionResponse = client
-                    .generate_content(provider, model, convo.clone().try_into()?)
-                    .await?;
-
-                let choice = resp.choices.first();
-                if choice.is_none() {
-                    warn!("No response from the assistant. Exiting...");
-                    break;
-                }
-                let choice = choice.unwrap();
-
-                let assistant_message = utils::strip_thinking(&choice.message.content);
-                if assistant_message.is_none() {
-                    warn!("Assistant message is empty. Exiting...");
-                    break;
-                }
-
-                let assistant_message = assistant_message.unwrap().trim().to_string();
-
-                convo.add_message(Message {
-                    role: MessageRole::Assistant,
-                    content: assistant_message.clone(),
-                    ..Default::default()
-                });
-
-                info!("Assistant: {}", assistant_message);
-                info!("Current tokens usage: {}", convo.get_current_tokens()?);
-
-                if let Some(tool_calls) = &choice.message.tool_calls {
-                    for tool_call in tool_calls {
-                        let tool = tools::Tools::from_str(tool_call.function.name.as_str())?;
-                        let args: serde_json::Value = tool_call.function.parse_arguments()?;
-                        let tool_result =
-                            tools::handle_tool_calls(&tool, Some(args), &config).await;
-                        if tool_result.is_err() {
-                            warn!("Tool failed to execute. Exiting...");
-                            let tool_message = Message {
-                                role: MessageRole::Tool,
-                                content: tool_result.unwrap_err().to_string(),
-                                tool_call_id: Some(tool_call.id.clone()),
-                                ..Default::default()
-                            };
-                            let user_message = Message {
-                                role: MessageRole::User,
-                                content:
-                                    "Something went wrong can you retry it? maybe take a step back."
-                                        .to_string(),
-                                ..Default::default()
-                            };
-                            debug!("Tool message(faliure): {:?}", tool_message);
-                            debug!("User message(faliure): {:?}", user_message);
-                            convo.add_message(tool_message);
-                            convo.add_message(user_message);
-                            continue;
-                        }
-
-                        let result = tool_result?;
-
-                        debug!("Tool result: {}", result);
+            info!("Starting autonomous mode...");
 
-                        let tool_message = Message {
-                            role: MessageRole::Tool,
-                            content: result.to_string(),
-                            tool_call_id: Some(tool_call.id.clone()),
-                            ..Default::default()
-                        };
+            let scm = scm::from_config(&config.scm)?;
+            let state_path = coder_dir.join(auto::PROCESSED_FILE);
+            let mut processed = auto::ProcessedIssues::load(&state_path)?;
+            let filter = auto::issue_filter(scm.as_ref(), labels.as_deref()).await?;
 
-                        let tool_result_struct: tools::StatusResponse =
-                            serde_json::from_value(result)?;
+            let mut processed_count = 0;
 
-                        if tool_result_struct.message == Some("Task completed".to_string()) {
-                            info!("Task completed. Exiting...");
-                            break 'outer;
+            loop {
+                info!("Checking for assigned issues...");
+
+                match scm.list_issues(&filter).await {
+                    Ok(issues) => {
+                        let issues: Vec<_> = issues
+                            .into_iter()
+                            .filter(|issue| !processed.is_settled(issue.number))
+                            .collect();
+
+                        if issues.is_empty() {
+                            info!("No new issues. Waiting for next check...");
+                        } else {
+                            info!("Found {} new issues", issues.len());
                         }
 
-                        let mut user_message = Message {
-                            role: MessageRole::User,
-                            content: "Let's proceed to the next step.".to_string(),
-                            ..Default::default()
-                        };
-                        if tool_result_struct.retry {
-                            warn!("Tool requires retry. Exiting...");
-                            user_message = Message {
-                                role: MessageRole::User,
-                                content: "Something went wrong can you retry it?".to_string(),
-                                ..Default::default()
+                        for issue in issues {
+                            info!("Processing issue #{}", issue.number);
+
+                            let failure = match fix_issue(&config, scm.as_ref(), issue.number).await
+                            {
+                                Ok(AgentStatus::Completed) => None,
+                                Ok(AgentStatus::BudgetExhausted(budget)) => {
+                                    Some(format!("the agent {}", budget))
+                                }
+                                Ok(status) => Some(format!("the agent stopped with {:?}", status)),
+                                Err(e) => Some(e.to_string()),
+                            };
+                            let outcome = match &failure {
+                                None => auto::Outcome::Done,
+                                Some(_) => auto::Outcome::Failed,
                             };
+                            let attempts = processed.record(issue.number, outcome);
+                            processed.save(&state_path)?;
+
+                            if let Some(reason) = &failure {
+                                warn!(
+                                    "Failed to process issue #{} (attempt {}/{}): {}",
+                                    issue.number,
+                                    attempts,
+                                    auto::MAX_ATTEMPTS,
+                                    reason
+                                );
+                            }
+                            // Only the last attempt is reported, retries stay quiet
+                            if let Some(reason) = failure.filter(|_| attempts >= auto::MAX_ATTEMPTS)
+                            {
+                                if let Err(e) = auto::report_failure(
+                                    scm.as_ref(),
+                                    issue.number,
+                                    &reason,
+                                    config.scm.failed_label.as_deref(),
+                                )
+                                .await
+                                {
+                                    warn!("Failed to report on issue #{}: {}", issue.number, e);
+                                }
+                            }
+
+                            processed_count += 1;
+
+                            if limit > 0 && processed_count >= limit {
+                                info!("Reached processing limit of {} issues", limit);
+                                return Ok(());
+                            }
                         }
-
-                        debug!("Tool message: {:?}", tool_message);
-                        debug!("User message: {:?}", user_message);
-                        convo.add_message(tool_message);
-                        convo.add_message(user_message);
                     }
+                    Err(e) => warn!("Failed to fetch issues: {}", e),
                 }
 
-                info!("Iteration completed. Developer is taking a coffee break due to rate-limiting..");
-
-                sleep(Duration::from_secs(60));
+                info!("Waiting {} seconds before next check...", interval);
+                tokio::time::sleep(Duration::from_secs(interval)).await;
             }
         }
+        Commands::Fix {
+            issue,
+            further_instruction,
+        } => {
+            info!("Fixing issue #{}...", issue);
+            info!("Further instructions: {:?}", further_instruction);
+
+            let scm = scm::from_config(&config.scm)?;
+            fix_issue(&config, scm.as_ref(), issue.into())
+                .await?
+                .into_result()?;
+        }
         Commands::Refactor { file } => {
             match file {
                 Some(path) => info!("Refactoring file: {}", path),
                 None => info!("Refactoring entire project..."),
             }
 
-            let client = InferenceGatewayClient::new(&config.api.endpoint)
-                .with_max_tokens(Some(900))
-                .with_tools(Some(tools));
+            let scm = scm::from_config(&config.scm)?;
+            let worktree = worktree::Worktree::create(
+                &env::current_dir()?,
+                &uuid::Uuid::new_v4().to_string(),
+                "HEAD",
+            )
+            .await?;
+            let status = refactor(&config, scm.as_ref(), &worktree).await;
+            worktree.remove().await;
+            status?.into_result()?;
+        }
+    }
 
-            let mut convo = Conversation::new(
-                config.agent.model.to_string(),
-                provider,
-                config.agent.max_tokens,
-            );
+    Ok(())
+}
 
-            setup_panic_handler(convo.clone());
+/// Run the agent refactoring the code of the worktree
+async fn refactor(
+    config: &config::Config,
+    scm: &dyn ScmProvider,
+    worktree: &worktree::Worktree,
+) -> Result<AgentStatus, CoderError> {
+    let mut agent = Agent::new(config, scm, tools::get_tools())?.with_worktree(worktree);
 
-            let system_prompt = format!(
-                r#"You are a senior software engineer specializing in Rust development. Your task is to refactor the code based on the provided code snippet. Keep your answers short and consice. Do not ask questions back.
+    let system_prompt = format!(
+        r#"You are a senior software engineer specializing in Rust development. Your task is to refactor the code based on the provided code snippet. Keep your answers short and consice. Do not ask questions back.
 
 WORKSPACE INFO:
 
 {}
 
+CHANGES SCOPE:
+{}
+
 WORKFLOW:
 1. Read the provided file content
 2. Analyse the code
@@ -394,84 +371,19 @@ WORKFLOW:
 4. Test the code
 5. Refactor the code
 6. Test the code again
-7. Finally, create a GitHub Pull Request
+7. Commit each logical change with a conventional commit message
+8. Finally, create a GitHub Pull Request
+9. Call "done" when complete
 
 "#,
-                index::build_tree()?,
-            );
+        index::build_tree(worktree.path())?,
+        scope::Scope::new(&config.agent.scope)?.describe(),
+    );
 
-            convo.add_message(Message {
-                role: MessageRole::System,
-                content: system_prompt,
-                ..Default::default()
-            });
-
-            convo.add_message(Message {
-                role: MessageRole::User,
-                content: "I need help refactoring this code snippet".to_string(),
-                ..Default::default()
-            });
-
-            let timeout = Duration::from_secs(300);
-            info!("Starting AI Coder agent...");
-            info!("Press Ctrl+C to stop the agent.");
-            loop {
-                if timeout.as_secs() == 0 {
-                    warn!("Timeout reached. Exiting...");
-                    break;
-                }
-
-                let resp: inference_gateway_sdk::CreateChatCompletionResponse = client
-                    .generate_content(
-                        provider,
-                        config.agent.model.as_str(),
-                        convo.clone().try_into()?,
-                    )
-                    .await?;
-
-                let choice = resp.choices.first();
-                if choice.is_none() {
-                    warn!("No response from the assistant. Exiting...");
-                    break;
-                }
-                let choice = choice.unwrap();
+    agent.system(system_prompt);
+    agent.user("I need help refactoring this code snippet".to_string());
 
-                let assistant_message = utils::strip_thinking(&choice.message.content);
-                if assistant_message.is_none() {
-                    warn!("Assistant message is empty. Exiting...");
-                    break;
-                }
-
-                let assistant_message = assistant_message.unwrap().trim().to_string();
-
-                convo.add_message(Message {
-                    role: MessageRole::Assistant,
-                    content: assistant_message.clone(),
-                    ..Default::default()
-                });
-
-                info!("Assistant: {}", assistant_message);
-
-                if let Some(ref tool_calls) = &choice.message.tool_calls {
-                    for tool_call in tool_calls {
-                        let tool = tools::Tools::from_str(tool_call.function.name.as_str())?;
-                        let args = tool_call.function.parse_arguments()?;
-                        let tool_result =
-                            tools::handle_tool_calls(&tool, Some(args), &config).await?;
-
-                        convo.add_message(Message {
-                            role: MessageRole::Tool,
-                            content: tool_result.to_string(),
-                            tool_call_id: Some(tool_call.id.clone()),
-                            ..Default::default()
-                        });
-                    }
-                }
-            }
-        }
-    }
-
-    Ok(())
+    agent.run().await
 }
 
 #[cfg(test)]
@@ -0,0 +1,391 @@
+use log::{debug, warn};
+use std::{
+    collections::{BTreeSet, VecDeque},
+    io,
+    process::Stdio,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    process::Command,
+    task::JoinHandle,
+};
+
+/// How long to wait for the output pipes to close once the process has exited or was killed,
+/// a background process holding them open must not hang the agent
+const PIPE_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+/// Process groups of the commands currently running, killed when coder shuts down
+static RUNNING_GROUPS: Mutex<BTreeSet<u32>> = Mutex::new(BTreeSet::new());
+
+#[derive(Debug)]
+pub struct Output {
+    /// Exit code, `None` when the process was killed by a signal
+    pub exit_code: Option<i32>,
+    pub success: bool,
+    pub stdout: String,
+    pub stderr: String,
+    /// Whether stdout or stderr was cut to the output cap
+    pub truncated: bool,
+    pub timed_out: bool,
+}
+
+/// Keeps the start and the end of a stream, where commands print what they run and their summary
+struct Capture {
+    head: Vec<u8>,
+    tail: VecDeque<u8>,
+    head_bytes: usize,
+    tail_bytes: usize,
+    omitted: usize,
+}
+
+impl Capture {
+    fn new(max_bytes: usize) -> Self {
+        let head_bytes = max_bytes / 4;
+        Self {
+            head: Vec::new(),
+            tail: VecDeque::new(),
+            head_bytes,
+            tail_bytes: max_bytes - head_bytes,
+            omitted: 0,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        let in_head = self
+            .head_bytes
+            .saturating_sub(self.head.len())
+            .min(bytes.len());
+        self.head.extend_from_slice(&bytes[..in_head]);
+        self.tail.extend(&bytes[in_head..]);
+        let overflow = self.tail.len().saturating_sub(self.tail_bytes);
+        self.tail.drain(..overflow);
+        self.omitted += overflow;
+    }
+
+    fn text(&self) -> (String, bool) {
+        let head = String::from_utf8_lossy(&self.head);
+        let tail: Vec<u8> = self.tail.iter().copied().collect();
+        let tail = String::from_utf8_lossy(&tail);
+        if self.omitted == 0 {
+            (format!("{}{}", head, tail), false)
+        } else {
+            (
+                format!("{}\n... {} bytes omitted ...\n{}", head, self.omitted, tail),
+                true,
+            )
+        }
+    }
+}
+
+fn read_into<R>(reader: Option<R>, capture: Arc<Mutex<Capture>>) -> JoinHandle<()>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let Some(mut reader) = reader else {
+            return;
+        };
+        let mut buffer = [0; 8192];
+        while let Ok(read @ 1..) = reader.read(&mut buffer).await {
+            if let Ok(mut capture) = capture.lock() {
+                capture.push(&buffer[..read]);
+            }
+        }
+    })
+}
+
+/// Kill the process and everything it started
+fn kill_process_group(pid: Option<u32>) {
+    #[cfg(unix)]
+    if let Some(pid) = pid {
+        // SAFETY: killpg only sends a signal and takes no pointers. The pid is the one of a
+        // child that leads its own process group (see `run`) and has not been reaped yet, so
+        // the group id cannot have been reused by an unrelated process.
+        let result = unsafe { libc::killpg(pid as libc::pid_t, libc::SIGKILL) };
+        if result != 0 {
+            let error = io::Error::last_os_error();
+            if error.raw_os_error() == Some(libc::ESRCH) {
+                debug!("Process group {} already exited", pid);
+            } else {
+                warn!("Failed to kill process group {}: {}", pid, error);
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = pid;
+}
+
+/// Process group of a running command, registered so it can be killed on shutdown
+///
+/// Dropping it before the command finished, e.g. because the agent's wall time ran out while
+/// awaiting `run`, kills the whole group: `kill_on_drop` only kills the direct child.
+struct RunningGroup {
+    pid: Option<u32>,
+    finished: bool,
+}
+
+impl RunningGroup {
+    fn register(pid: Option<u32>) -> Self {
+        if let (Some(pid), Ok(mut groups)) = (pid, RUNNING_GROUPS.lock()) {
+            groups.insert(pid);
+        }
+        Self {
+            pid,
+            finished: false,
+        }
+    }
+}
+
+impl Drop for RunningGroup {
+    fn drop(&mut self) {
+        if !self.finished {
+            kill_process_group(self.pid);
+        }
+        if let (Some(pid), Ok(mut groups)) = (self.pid, RUNNING_GROUPS.lock()) {
+            groups.remove(&pid);
+        }
+    }
+}
+
+/// Kill the process groups of all running commands
+pub fn kill_running() {
+    let groups: Vec<u32> = RUNNING_GROUPS
+        .lock()
+        .map(|groups| groups.iter().copied().collect())
+        .unwrap_or_default();
+    for pid in groups {
+        kill_process_group(Some(pid));
+    }
+}
+
+/// Kill the running commands and exit when coder is interrupted (Ctrl+C) or terminated
+///
+/// Commands run in their own process groups, outside of the terminal's foreground group, so
+/// they do not receive the interrupt themselves and would keep running after coder exits.
+pub fn kill_running_on_shutdown() {
+    tokio::spawn(async {
+        #[cfg(unix)]
+        let terminate = async {
+            use tokio::signal::unix::{signal, SignalKind};
+            match signal(SignalKind::terminate()) {
+                Ok(mut terminate) => terminate.recv().await,
+                Err(e) => {
+                    warn!("Failed to listen for SIGTERM: {}", e);
+                    std::future::pending().await
+                }
+            }
+        };
+        #[cfg(not(unix))]
+        let terminate = std::future::pending::<Option<()>>();
+
+        let interrupt = Box::pin(tokio::signal::ctrl_c());
+        let code = match futures_util::future::select(interrupt, Box::pin(terminate)).await {
+            futures_util::future::Either::Left(_) => 130,
+            futures_util::future::Either::Right(_) => 143,
+        };
+        warn!("Shutting down, killing the running commands");
+        kill_running();
+        std::process::exit(code);
+    });
+}
+
+/// Run a command without blocking the runtime, enforcing a timeout and an output cap
+///
+/// # Arguments
+///
+/// * `command` - Command to run, stdin is closed and the output is captured
+/// * `timeout` - Time after which the process group of the command is killed
+/// * `max_output_bytes` - Bytes kept of stdout and of stderr each, the middle is cut
+///
+/// # Returns
+///
+/// * `io::Result<Output>` - The output so far when the command timed out, an error only when
+///   the command could not be started
+pub async fn run(
+    mut command: Command,
+    timeout: Duration,
+    max_output_bytes: usize,
+) -> io::Result<Output> {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    #[cfg(unix)]
+    command.process_group(0);
+
+    let mut child = command.spawn()?;
+    let mut group = RunningGroup::register(child.id());
+    let stdout = Arc::new(Mutex::new(Capture::new(max_output_bytes)));
+    let stderr = Arc::new(Mutex::new(Capture::new(max_output_bytes)));
+    let readers = [
+        read_into(child.stdout.take(), stdout.clone()),
+        read_into(child.stderr.take(), stderr.clone()),
+    ];
+
+    let (status, timed_out) = match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(status) => (Some(status?), false),
+        Err(_) => {
+            warn!("Command timed out after {:?}, killing it", timeout);
+            kill_process_group(child.id());
+            child.kill().await?;
+            (None, true)
+        }
+    };
+    group.finished = true;
+    drop(group);
+
+    for reader in readers {
+        let abort = reader.abort_handle();
+        if tokio::time::timeout(PIPE_GRACE_PERIOD, reader)
+            .await
+            .is_err()
+        {
+            abort.abort();
+        }
+    }
+
+    let (stdout, stdout_truncated) = stdout.lock().map(|c| c.text()).unwrap_or_default();
+    let (stderr, stderr_truncated) = stderr.lock().map(|c| c.text()).unwrap_or_default();
+    Ok(Output {
+        exit_code: status.and_then(|s| s.code()),
+        success: status.is_some_and(|s| s.success()),
+        stdout,
+        stderr,
+        truncated: stdout_truncated || stderr_truncated,
+        timed_out,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn shell(script: &str) -> Command {
+        let mut command = Command::new("sh");
+        command.args(["-c", script]);
+        command
+    }
+
+    #[test]
+    fn test_capture_keeps_head_and_tail() {
+        let mut capture = Capture::new(100);
+        capture.push(b"short");
+        assert_eq!(capture.text(), ("short".to_string(), false));
+
+        capture.push(format!("\n{}\n", "x".repeat(1000)).as_bytes());
+        capture.push(b"test result: FAILED");
+        let (text, truncated) = capture.text();
+        assert!(truncated);
+        assert!(text.starts_with("short"));
+        assert!(text.ends_with("test result: FAILED"));
+        assert!(text.contains("bytes omitted"));
+        assert!(text.len() < 150);
+    }
+
+    #[tokio::test]
+    async fn test_run_captures_output() -> Result<(), Box<dyn std::error::Error>> {
+        let output = run(
+            shell("echo out; echo err >&2; exit 3"),
+            Duration::from_secs(10),
+            1024,
+        )
+        .await?;
+
+        assert_eq!(output.exit_code, Some(3));
+        assert!(!output.success);
+        assert_eq!(output.stdout, "out\n");
+        assert_eq!(output.stderr, "err\n");
+        assert!(!output.truncated);
+        assert!(!output.timed_out);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_caps_output() -> Result<(), Box<dyn std::error::Error>> {
+        let output = run(
+            shell("seq 1 100000; echo done"),
+            Duration::from_secs(10),
+            1024,
+        )
+        .await?;
+
+        assert!(output.success);
+        assert!(output.truncated);
+        assert!(output.stdout.starts_with("1\n2\n"));
+        assert!(output.stdout.ends_with("100000\ndone\n"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_kills_process_group_on_timeout() -> Result<(), Box<dyn std::error::Error>> {
+        let started = Instant::now();
+        // The background sleep keeps the output pipes open unless the whole group is killed
+        let output = run(
+            shell("echo started; sleep 30 & sleep 30"),
+            Duration::from_millis(500),
+            1024,
+        )
+        .await?;
+
+        assert!(output.timed_out);
+        assert!(!output.success);
+        assert_eq!(output.exit_code, None);
+        assert_eq!(output.stdout, "started\n");
+        assert!(started.elapsed() < PIPE_GRACE_PERIOD);
+        Ok(())
+    }
+
+    /// Whether the process exists and is not a zombie waiting to be reaped
+    #[cfg(unix)]
+    fn is_alive(pid: libc::pid_t) -> bool {
+        match std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+            // The state follows the parenthesized command name
+            Ok(stat) => stat
+                .rsplit_once(") ")
+                .is_some_and(|(_, rest)| !rest.starts_with('Z')),
+            // SAFETY: signal 0 only checks whether the process exists
+            Err(_) => unsafe { libc::kill(pid, 0) == 0 },
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_abandoned_run_kills_process_group() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let pid_file = dir.path().join("pid");
+        let script = format!("sleep 30 & echo $! > '{}'; wait", pid_file.display());
+
+        let abandoned = tokio::time::timeout(
+            Duration::from_millis(500),
+            run(shell(&script), Duration::from_secs(60), 1024),
+        )
+        .await;
+        assert!(abandoned.is_err());
+
+        let pid: libc::pid_t = std::fs::read_to_string(&pid_file)?.trim().parse()?;
+        let started = Instant::now();
+        while is_alive(pid) {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "background process survived"
+            );
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_fails_for_missing_program() {
+        let result = run(
+            Command::new("definitely-not-a-program"),
+            Duration::from_secs(1),
+            1024,
+        )
+        .await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,258 @@
+use ignore::gitignore::Gitignore;
+use std::path::{Component, Path, PathBuf};
+
+use crate::errors::CoderError;
+
+/// Directories tools must never write into
+const PROTECTED_DIRS: [&str; 2] = [".git", ".coder"];
+
+/// Path the agent wants to change, with the path it leads to once symbolic links are followed
+#[derive(Debug, PartialEq)]
+pub struct WritablePath {
+    /// Normalized path relative to the root, a link itself when it names one
+    pub path: PathBuf,
+    /// Path relative to the root that is written when following the links on the way
+    pub target: PathBuf,
+}
+
+/// Resolve a tool supplied path against the repository root
+///
+/// The path is normalized lexically, `..` components may not leave the root and every existing
+/// component is checked so symlinks cannot point outside of it. Dangling symlinks are rejected.
+///
+/// # Arguments
+///
+/// * `root` - Repository root
+/// * `path` - Relative path, or absolute path inside the root
+///
+/// # Returns
+///
+/// * `Result<PathBuf, CoderError>` - The normalized path relative to the root
+pub fn resolve(root: &Path, path: &str) -> Result<PathBuf, CoderError> {
+    resolve_target(root, path).map(|resolved| resolved.path)
+}
+
+fn resolve_target(root: &Path, path: &str) -> Result<WritablePath, CoderError> {
+    let canonical_root = root.canonicalize()?;
+    let requested = Path::new(path);
+
+    let relative = if requested.is_absolute() {
+        let absolute = normalize(requested, path)?;
+        absolute
+            .strip_prefix(&canonical_root)
+            .or_else(|_| absolute.strip_prefix(root))
+            .map(Path::to_path_buf)
+            .map_err(|_| CoderError::PathError(format!("{} is outside of the repository", path)))?
+    } else {
+        normalize(requested, path)?
+    };
+
+    if relative.as_os_str().is_empty() {
+        return Err(CoderError::PathError(format!(
+            "{} does not point to a file in the repository",
+            path
+        )));
+    }
+
+    // Follow the existing components one by one, a symlink may point anywhere and a dangling
+    // one would be followed by a write to create its target
+    let mut current = canonical_root.clone();
+    let mut components = relative.components();
+    while let Some(component) = components.next() {
+        let next = current.join(component);
+        // Nothing below a missing component can be a link
+        let Ok(metadata) = next.symlink_metadata() else {
+            current = next;
+            if !components.as_path().as_os_str().is_empty() {
+                current.push(components.as_path());
+            }
+            break;
+        };
+        current = if metadata.file_type().is_symlink() {
+            next.canonicalize().map_err(|_| {
+                CoderError::PathError(format!(
+                    "{} goes through a symbolic link whose target does not exist",
+                    path
+                ))
+            })?
+        } else {
+            next
+        };
+        if !current.starts_with(&canonical_root) {
+            return Err(CoderError::PathError(format!(
+                "{} resolves outside of the repository",
+                path
+            )));
+        }
+    }
+
+    let target = current
+        .strip_prefix(&canonical_root)
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    Ok(WritablePath {
+        path: relative,
+        target,
+    })
+}
+
+/// Resolve a path like `resolve`, additionally rejecting paths that are, or whose symbolic links
+/// lead, inside a `.git` or `.coder` directory at any depth (e.g. a submodule's `.git`)
+pub fn resolve_writable(root: &Path, path: &str) -> Result<WritablePath, CoderError> {
+    let resolved = resolve_target(root, path)?;
+    if is_protected(&resolved.path) || is_protected(&resolved.target) {
+        return Err(CoderError::PathError(format!(
+            "{} is inside a protected directory and cannot be modified",
+            path
+        )));
+    }
+    Ok(resolved)
+}
+
+fn is_protected(path: &Path) -> bool {
+    path.components().any(|component| {
+        matches!(component, Component::Normal(name) if PROTECTED_DIRS.iter().any(|dir| name == *dir))
+    })
+}
+
+/// Whether a path relative to the root is excluded by a `.gitignore` on the way to it
+///
+/// Deeper `.gitignore` files take precedence, so a nested whitelist (`!file`) wins.
+pub fn is_ignored(root: &Path, relative: &Path) -> bool {
+    let mut ignored = false;
+    let dirs: Vec<&Path> = relative.ancestors().skip(1).collect();
+    for dir in dirs.into_iter().rev() {
+        let gitignore_path = root.join(dir).join(".gitignore");
+        if !gitignore_path.is_file() {
+            continue;
+        }
+        let (gitignore, _) = Gitignore::new(&gitignore_path);
+        let Ok(path) = relative.strip_prefix(dir) else {
+            continue;
+        };
+        let matched = gitignore.matched_path_or_any_parents(path, root.join(relative).is_dir());
+        if matched.is_ignore() {
+            ignored = true;
+        } else if matched.is_whitelist() {
+            ignored = false;
+        }
+    }
+    ignored
+}
+
+/// Remove `.` components and apply `..` without touching the filesystem
+fn normalize(path: &Path, original: &str) -> Result<PathBuf, CoderError> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return Err(CoderError::PathError(format!(
+                        "{} escapes the repository",
+                        original
+                    )));
+                }
+            }
+            other => normalized.push(other),
+        }
+    }
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_resolve_relative_paths() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        assert_eq!(
+            resolve(dir.path(), "src/main.rs")?,
+            Path::new("src/main.rs")
+        );
+        assert_eq!(resolve(dir.path(), "./src/../lib.rs")?, Path::new("lib.rs"));
+
+        let absolute = dir.path().canonicalize()?.join("src/lib.rs");
+        assert_eq!(
+            resolve(dir.path(), absolute.to_str().unwrap())?,
+            Path::new("src/lib.rs")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_rejects_escapes() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        assert!(resolve(dir.path(), "../../etc/whatever").is_err());
+        assert!(resolve(dir.path(), "src/../../outside").is_err());
+        assert!(resolve(dir.path(), "/etc/passwd").is_err());
+        assert!(resolve(dir.path(), ".").is_err());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_rejects_symlink_escapes() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        let outside = tempdir()?;
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("link"))?;
+
+        let result = resolve(dir.path(), "link/file.txt");
+        assert!(result.unwrap_err().to_string().contains("resolves outside"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_ignored() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        fs::write(dir.path().join(".gitignore"), "target/\n*.log\n")?;
+        fs::create_dir_all(dir.path().join("logs"))?;
+        fs::write(dir.path().join("logs/.gitignore"), "!keep.log\n")?;
+
+        assert!(is_ignored(dir.path(), Path::new("target/debug/coder")));
+        assert!(is_ignored(dir.path(), Path::new("build.log")));
+        assert!(!is_ignored(dir.path(), Path::new("logs/keep.log")));
+        assert!(!is_ignored(dir.path(), Path::new("src/main.rs")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_writable_rejects_protected_dirs() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        fs::create_dir_all(dir.path().join(".git"))?;
+
+        assert!(resolve_writable(dir.path(), ".git/config").is_err());
+        assert!(resolve_writable(dir.path(), "./.coder/config.yaml").is_err());
+        assert!(resolve_writable(dir.path(), "vendor/lib/.git/config").is_err());
+        assert!(resolve_writable(dir.path(), "src/.git.rs").is_ok());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_writable_follows_symlinks() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        fs::create_dir_all(dir.path().join(".git/hooks"))?;
+        fs::create_dir_all(dir.path().join("src"))?;
+        fs::write(dir.path().join("Cargo.toml"), "[package]")?;
+        std::os::unix::fs::symlink(dir.path().join(".git/hooks"), dir.path().join("hooks"))?;
+        std::os::unix::fs::symlink("../Cargo.toml", dir.path().join("src/manifest"))?;
+
+        assert!(resolve_writable(dir.path(), "hooks/pre-commit").is_err());
+        assert_eq!(
+            resolve_writable(dir.path(), "src/manifest")?,
+            WritablePath {
+                path: PathBuf::from("src/manifest"),
+                target: PathBuf::from("Cargo.toml"),
+            }
+        );
+        assert_eq!(
+            resolve_writable(dir.path(), "src/new/lib.rs")?.target,
+            PathBuf::from("src/new/lib.rs")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,262 @@
+use async_trait::async_trait;
+use serde::Serialize;
+
+use crate::config::ScmConfig;
+use crate::errors::CoderError;
+use crate::github::GitHub;
+use crate::gitlab::{self, GitLab};
+
+/// Issue details shared across SCM backends
+#[derive(Debug, Clone, Serialize)]
+pub struct Issue {
+    pub number: u64,
+    pub title: String,
+    pub body: Option<String>,
+}
+
+/// Pull (or merge) request details shared across SCM backends
+#[derive(Debug, Clone, Serialize)]
+pub struct PullRequest {
+    pub number: u64,
+    pub title: Option<String>,
+    pub body: Option<String>,
+    pub url: Option<String>,
+}
+
+/// Criteria for listing open issues
+#[derive(Debug, Clone, Default)]
+pub struct IssueFilter {
+    /// Username the issues must be assigned to
+    pub assignee: Option<String>,
+    /// Labels the issues must all carry
+    pub labels: Vec<String>,
+}
+
+/// Issue and pull request operations the agent needs from a forge
+#[async_trait]
+pub trait ScmProvider: Send + Sync {
+    /// Username of the authenticated account
+    async fn current_user(&self) -> Result<String, CoderError>;
+
+    /// Get a single issue by number
+    async fn get_issue(&self, number: u64) -> Result<Issue, CoderError>;
+
+    /// List open issues matching the filter
+    async fn list_issues(&self, filter: &IssueFilter) -> Result<Vec<Issue>, CoderError>;
+
+    /// Open a pull request from `head` into `base`
+    async fn create_pull_request(
+        &self,
+        head: &str,
+        base: &str,
+        title: &str,
+        body: &str,
+    ) -> Result<PullRequest, CoderError>;
+
+    /// Open pull request from `head` (a branch, or owner:branch for a fork), if there is one
+    async fn find_pull_request(&self, head: &str) -> Result<Option<PullRequest>, CoderError>;
+
+    /// Update the title and/or body of an existing pull request
+    async fn update_pull_request(
+        &self,
+        number: u64,
+        title: Option<&str>,
+        body: Option<&str>,
+    ) -> Result<PullRequest, CoderError>;
+
+    /// Comment on an issue
+    async fn comment(&self, issue: u64, body: &str) -> Result<(), CoderError>;
+
+    /// Add labels to an issue
+    async fn add_labels(&self, issue: u64, labels: &[String]) -> Result<(), CoderError>;
+}
+
+fn token() -> Result<String, CoderError> {
+    std::env::var("CODER_SCM_TOKEN")
+        .map_err(|_| CoderError::ConfigError("CODER_SCM_TOKEN not set".to_string()))
+}
+
+/// Build the provider selected by `scm.name`
+pub fn from_config(scm: &ScmConfig) -> Result<Box<dyn ScmProvider>, CoderError> {
+    match scm.name.as_str() {
+        "github" => Ok(Box::new(GitHub::new(
+            scm.url.as_deref(),
+            &token()?,
+            &scm.owner,
+            &scm.repository,
+        )?)),
+        "gitlab" => Ok(Box::new(GitLab::new(
+            scm.url.as_deref().unwrap_or(gitlab::DEFAULT_URL),
+            &token()?,
+            &scm.owner,
+            &scm.repository,
+        ))),
+        name => Err(CoderError::ConfigError(format!(
+            "Unsupported SCM: {}",
+            name
+        ))),
+    }
+}
+
+#[cfg(test)]
+pub mod fake {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// In-memory provider for exercising tools without a forge
+    #[derive(Default)]
+    pub struct InMemoryScm {
+        pub issues: Mutex<Vec<Issue>>,
+        pub pull_requests: Mutex<Vec<(String, String, PullRequest)>>,
+        pub comments: Mutex<Vec<(u64, String)>>,
+        pub labels: Mutex<Vec<(u64, String)>>,
+    }
+
+    impl InMemoryScm {
+        pub fn with_issues(issues: Vec<Issue>) -> Self {
+            Self {
+                issues: Mutex::new(issues),
+                ..Default::default()
+            }
+        }
+    }
+
+    #[async_trait]
+    impl ScmProvider for InMemoryScm {
+        async fn current_user(&self) -> Result<String, CoderError> {
+            Ok("coder-bot".to_string())
+        }
+
+        async fn get_issue(&self, number: u64) -> Result<Issue, CoderError> {
+            self.issues
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|issue| issue.number == number)
+                .cloned()
+                .ok_or_else(|| CoderError::ConfigError(format!("Issue #{} not found", number)))
+        }
+
+        async fn list_issues(&self, filter: &IssueFilter) -> Result<Vec<Issue>, CoderError> {
+            let labels = self.labels.lock().unwrap();
+            Ok(self
+                .issues
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|issue| {
+                    filter.labels.iter().all(|label| {
+                        labels
+                            .iter()
+                            .any(|(number, l)| *number == issue.number && l == label)
+                    })
+                })
+                .cloned()
+                .collect())
+        }
+
+        async fn create_pull_request(
+            &self,
+            head: &str,
+            base: &str,
+            title: &str,
+            body: &str,
+        ) -> Result<PullRequest, CoderError> {
+            let mut pull_requests = self.pull_requests.lock().unwrap();
+            let pr = PullRequest {
+                number: pull_requests.len() as u64 + 1,
+                title: Some(title.to_string()),
+                body: Some(body.to_string()),
+                url: None,
+            };
+            pull_requests.push((head.to_string(), base.to_string(), pr.clone()));
+            Ok(pr)
+        }
+
+        async fn find_pull_request(&self, head: &str) -> Result<Option<PullRequest>, CoderError> {
+            Ok(self
+                .pull_requests
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|(h, _, _)| h == head)
+                .map(|(_, _, pr)| pr.clone()))
+        }
+
+        async fn update_pull_request(
+            &self,
+            number: u64,
+            title: Option<&str>,
+            body: Option<&str>,
+        ) -> Result<PullRequest, CoderError> {
+            let mut pull_requests = self.pull_requests.lock().unwrap();
+            let (_, _, pr) = pull_requests
+                .iter_mut()
+                .find(|(_, _, pr)| pr.number == number)
+                .ok_or_else(|| CoderError::ConfigError(format!("PR #{} not found", number)))?;
+            if let Some(title) = title {
+                pr.title = Some(title.to_string());
+            }
+            if let Some(body) = body {
+                pr.body = Some(body.to_string());
+            }
+            Ok(pr.clone())
+        }
+
+        async fn comment(&self, issue: u64, body: &str) -> Result<(), CoderError> {
+            self.comments
+                .lock()
+                .unwrap()
+                .push((issue, body.to_string()));
+            Ok(())
+        }
+
+        async fn add_labels(&self, issue: u64, labels: &[String]) -> Result<(), CoderError> {
+            self.labels
+                .lock()
+                .unwrap()
+                .extend(labels.iter().map(|label| (issue, label.clone())));
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+
+    fn scm_config(name: &str) -> ScmConfig {
+        ScmConfig {
+            name: name.to_string(),
+            owner: "owner".to_string(),
+            repository: "repo".to_string(),
+            issue_template: None,
+            ..crate::config::Config::default().scm
+        }
+    }
+
+    #[test]
+    #[serial]
+    fn test_from_config_requires_token() {
+        std::env::remove_var("CODER_SCM_TOKEN");
+        let result = from_config(&scm_config("github"));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_from_config_rejects_unknown_scm() {
+        std::env::set_var("CODER_SCM_TOKEN", "secret");
+        let result = from_config(&scm_config("bitbucket"));
+        std::env::remove_var("CODER_SCM_TOKEN");
+
+        match result {
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "Configuration error: Unsupported SCM: bitbucket"
+            ),
+            Ok(_) => panic!("Expected unsupported SCM error"),
+        }
+    }
+}
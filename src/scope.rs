@@ -0,0 +1,139 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::path::{Component, Path};
+
+use crate::config::ScopeConfig;
+use crate::errors::CoderError;
+
+/// Compiled `agent.scope` patterns deciding which files the agent may change
+#[derive(Debug)]
+pub struct Scope {
+    allow: GlobSet,
+    deny: GlobSet,
+    allow_patterns: Vec<String>,
+    deny_patterns: Vec<String>,
+}
+
+fn build_set(patterns: &[String]) -> Result<GlobSet, CoderError> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|e| {
+            CoderError::ConfigError(format!("Invalid scope pattern '{}': {}", pattern, e))
+        })?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|e| CoderError::ConfigError(format!("Invalid scope patterns: {}", e)))
+}
+
+impl Scope {
+    /// Compile the allow and deny patterns of the config
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Scope section of the agent config
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Scope, CoderError>` - The compiled scope, or an error for invalid patterns
+    pub fn new(config: &ScopeConfig) -> Result<Self, CoderError> {
+        Ok(Self {
+            allow: build_set(&config.allow)?,
+            deny: build_set(&config.deny)?,
+            allow_patterns: config.allow.clone(),
+            deny_patterns: config.deny.clone(),
+        })
+    }
+
+    /// Whether the path or one of its parent directories matches the set
+    fn matches(set: &GlobSet, path: &Path) -> bool {
+        path.ancestors()
+            .filter(|p| !p.as_os_str().is_empty())
+            .any(|p| set.is_match(p))
+    }
+
+    /// Check whether a repository relative path may be changed
+    ///
+    /// A path is in scope when it (or a parent directory) matches an allowed pattern,
+    /// or no allowed patterns are configured, and it matches none of the denied patterns.
+    pub fn is_allowed(&self, path: &str) -> bool {
+        let path: std::path::PathBuf = Path::new(path)
+            .components()
+            .filter(|c| !matches!(c, Component::CurDir))
+            .collect();
+
+        let allowed = self.allow_patterns.is_empty() || Self::matches(&self.allow, &path);
+        allowed && !Self::matches(&self.deny, &path)
+    }
+
+    /// Human readable description of the scope, used in the system prompt
+    pub fn describe(&self) -> String {
+        let mut description = if self.allow_patterns.is_empty() {
+            "You are allowed to change any file in the repository.".to_string()
+        } else {
+            format!(
+                "The changes you are allowed to make are limited to files matching: {}.",
+                self.allow_patterns.join(", ")
+            )
+        };
+        if !self.deny_patterns.is_empty() {
+            description.push_str(&format!(
+                "\nYou must not change files matching: {}.",
+                self.deny_patterns.join(", ")
+            ));
+        }
+        description
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scope(allow: &[&str], deny: &[&str]) -> Scope {
+        Scope::new(&ScopeConfig {
+            allow: allow.iter().map(|s| s.to_string()).collect(),
+            deny: deny.iter().map(|s| s.to_string()).collect(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_empty_scope_allows_everything() {
+        let scope = scope(&[], &[]);
+        assert!(scope.is_allowed("src/main.rs"));
+        assert!(scope.is_allowed("README.md"));
+    }
+
+    #[test]
+    fn test_allow_and_deny_patterns() {
+        let scope = scope(&["src/**", "playground/bug1"], &["src/generated/**"]);
+        assert!(scope.is_allowed("src/main.rs"));
+        assert!(scope.is_allowed("./src/tools.rs"));
+        assert!(scope.is_allowed("playground/bug1/main.rs"));
+        assert!(!scope.is_allowed("playground/bug2/main.rs"));
+        assert!(!scope.is_allowed("Cargo.toml"));
+        assert!(!scope.is_allowed("src/generated/api.rs"));
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        let result = Scope::new(&ScopeConfig {
+            allow: vec!["src/[".to_string()],
+            deny: vec![],
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(
+            scope(&[], &[]).describe(),
+            "You are allowed to change any file in the repository."
+        );
+        assert_eq!(
+            scope(&["src/**"], &["*.lock"]).describe(),
+            "The changes you are allowed to make are limited to files matching: src/**.\nYou must not change files matching: *.lock."
+        );
+    }
+}
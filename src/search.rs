@@ -0,0 +1,193 @@
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+use crate::errors::CoderError;
+use crate::index;
+
+/// Maximum number of matches returned by a single search
+pub const MAX_SEARCH_RESULTS: usize = 50;
+
+/// Maximum number of context lines around a match
+pub const MAX_CONTEXT_LINES: usize = 10;
+
+/// Files larger than this are skipped, they are most likely generated or binary
+const MAX_FILE_BYTES: u64 = 1024 * 1024;
+
+/// A single matching line
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchMatch {
+    pub path: String,
+    /// 1-based line number
+    pub line: usize,
+    pub text: String,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchResults {
+    pub matches: Vec<SearchMatch>,
+    /// Whether the search stopped at `MAX_SEARCH_RESULTS`
+    pub truncated: bool,
+}
+
+/// Build the matcher for a literal or regex query
+///
+/// # Arguments
+///
+/// * `query` - Text or regular expression to look for
+/// * `is_regex` - Whether `query` is a regular expression
+/// * `ignore_case` - Match case insensitively
+///
+/// # Returns
+///
+/// * `Result<Regex, CoderError>` - The matcher, `CoderError::InvalidArguments` for an invalid regex
+pub fn matcher(query: &str, is_regex: bool, ignore_case: bool) -> Result<Regex, CoderError> {
+    if query.is_empty() {
+        return Err(CoderError::InvalidArguments(
+            "Search query cannot be empty".to_string(),
+        ));
+    }
+    let pattern = if is_regex {
+        query.to_string()
+    } else {
+        regex::escape(query)
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|e| CoderError::InvalidArguments(format!("Invalid regex: {}", e)))
+}
+
+/// Search the files under `path` for lines matching `matcher`
+///
+/// # Arguments
+///
+/// * `root` - Repository root, reported paths are relative to it
+/// * `path` - File or directory to search, relative to the root
+/// * `matcher` - Matcher built with `matcher`
+/// * `context` - Lines of context to include before and after each match
+///
+/// # Returns
+///
+/// * `SearchResults` - Matches in walk order, capped at `MAX_SEARCH_RESULTS`
+pub fn search(root: &Path, path: &Path, matcher: &Regex, context: usize) -> SearchResults {
+    let context = context.min(MAX_CONTEXT_LINES);
+    let mut results = SearchResults {
+        matches: Vec::new(),
+        truncated: false,
+    };
+
+    let walker = index::walk_builder(&root.join(path))
+        .max_filesize(Some(MAX_FILE_BYTES))
+        .sort_by_file_path(|a, b| a.cmp(b))
+        .build();
+    for entry in walker.flatten() {
+        if !entry.path().is_file() {
+            continue;
+        }
+        // Binary and non UTF-8 files are skipped
+        let Ok(content) = fs::read_to_string(entry.path()) else {
+            continue;
+        };
+        let relative = entry
+            .path()
+            .strip_prefix(root)
+            .unwrap_or(entry.path())
+            .to_string_lossy()
+            .to_string();
+
+        let lines: Vec<&str> = content.lines().collect();
+        for (i, line) in lines.iter().enumerate() {
+            if !matcher.is_match(line) {
+                continue;
+            }
+            if results.matches.len() == MAX_SEARCH_RESULTS {
+                results.truncated = true;
+                return results;
+            }
+            results.matches.push(SearchMatch {
+                path: relative.clone(),
+                line: i + 1,
+                text: line.to_string(),
+                before: lines[i.saturating_sub(context)..i]
+                    .iter()
+                    .map(|l| l.to_string())
+                    .collect(),
+                after: lines[i + 1..(i + 1 + context).min(lines.len())]
+                    .iter()
+                    .map(|l| l.to_string())
+                    .collect(),
+            });
+        }
+    }
+
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_search_literal_with_context() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        fs::create_dir_all(dir.path().join(".git"))?;
+        fs::create_dir_all(dir.path().join("src"))?;
+        fs::create_dir_all(dir.path().join("target"))?;
+        fs::write(dir.path().join(".gitignore"), "target/\n")?;
+        fs::write(
+            dir.path().join("src/lib.rs"),
+            "use std::fs;\n\nfn parse(a: &str) {\n    todo!()\n}\n",
+        )?;
+        fs::write(dir.path().join("target/out.rs"), "fn parse(a: &str) {}\n")?;
+
+        let results = search(
+            dir.path(),
+            Path::new(""),
+            &matcher("fn parse(", false, false)?,
+            1,
+        );
+
+        assert!(!results.truncated);
+        assert_eq!(results.matches.len(), 1);
+        let found = &results.matches[0];
+        assert_eq!(found.path, "src/lib.rs");
+        assert_eq!(found.line, 3);
+        assert_eq!(found.before, vec![""]);
+        assert_eq!(found.after, vec!["    todo!()"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_regex_is_capped() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        let content: String = (0..MAX_SEARCH_RESULTS + 10)
+            .map(|i| format!("let value_{} = {};\n", i, i))
+            .collect();
+        fs::write(dir.path().join("values.rs"), content)?;
+
+        let results = search(
+            dir.path(),
+            Path::new("values.rs"),
+            &matcher(r"VALUE_\d+", true, true)?,
+            0,
+        );
+
+        assert!(results.truncated);
+        assert_eq!(results.matches.len(), MAX_SEARCH_RESULTS);
+        assert!(results.matches[0].before.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matcher_rejects_invalid_regex() {
+        assert!(matches!(
+            matcher("fn (", true, false),
+            Err(CoderError::InvalidArguments(_))
+        ));
+        assert!(matcher("fn (", false, false).is_ok());
+    }
+}
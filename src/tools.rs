@@ -1,17 +1,28 @@
 use inference_gateway_sdk::{FunctionObject, Tool, ToolType};
 use log::{info, warn};
-use octocrab::Octocrab;
+use regex::Regex;
 use serde::{de::Error, Deserialize, Deserializer, Serialize};
 use serde_json::{json, Value};
 use std::{
     fmt::{self, Display},
-    path::Path,
-    process::Command,
+    path::{Path, PathBuf},
     str::FromStr,
+    time::Duration,
 };
 
 use crate::config;
+use crate::diagnostics;
+use crate::docs;
+use crate::edit::{self, SearchReplace};
 use crate::errors::CoderError;
+use crate::git;
+use crate::index;
+use crate::process;
+use crate::sandbox;
+use crate::scm::{Issue, PullRequest, ScmProvider};
+use crate::scope::Scope;
+use crate::search;
+use crate::workspace::Workspace;
 
 // Tool structure for language-agnostic code fixes
 #[derive(Debug, Clone)]
@@ -22,13 +33,22 @@ pub enum Tools {
 
     // Code specific tools
     CodeRead,    // Read file content
+    CodeSearch,  // Search file contents
+    CodeList,    // List files and directories
     CodeWrite,   // Write file content
+    CodeEdit,    // Edit file content with search/replace blocks or a diff
+    CodeDelete,  // Delete a file or directory
+    CodeMove,    // Move or rename a file or directory
     CodeAnalyse, // Analyse code (language-specific)
     CodeLint,    // Lint code (language-specific)
     CodeTest,    // Run tests (language-specific)
 
     // Version control
     PullRequest, // Create PR with fixes
+    GitCommit,   // Commit changed files
+    GitLog,      // Recent commits for a path
+    GitBlame,    // Last change of each line in a range
+    GitDiff,     // Working tree changes against the base branch
 
     // Documentation
     DocsReference, // Get documentation references
@@ -69,6 +89,52 @@ pub struct IssuePullArgs {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CodeReadArgs {
     pub path: String,
+    /// First line to read (1-based, inclusive)
+    pub start_line: Option<usize>,
+    /// Last line to read (1-based, inclusive)
+    pub end_line: Option<usize>,
+    /// Read the snapshot from .coder/index.yaml instead of the working tree
+    #[serde(default)]
+    pub from_index: bool,
+}
+
+/// Maximum number of bytes `code_read` returns in a single call
+pub const MAX_READ_BYTES: usize = 64 * 1024;
+
+/// Content returned by `code_read`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileContent {
+    pub path: String,
+    pub content: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub total_lines: usize,
+    /// Whether the content was cut at `MAX_READ_BYTES`
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CodeSearchArgs {
+    pub query: String,
+    /// Treat the query as a regular expression instead of literal text
+    #[serde(default)]
+    pub regex: bool,
+    #[serde(default)]
+    pub ignore_case: bool,
+    /// File or directory to search, defaults to the whole repository
+    pub path: Option<String>,
+    /// Lines of context around each match
+    pub context: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CodeListArgs {
+    /// File or directory to list, defaults to the repository root
+    pub path: Option<String>,
+    /// Directory levels to descend, defaults to 1
+    pub depth: Option<usize>,
+    /// Only include entries matching the glob (e.g. *.rs)
+    pub glob: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -77,13 +143,86 @@ pub struct CodeWriteArgs {
     pub content: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CodeEditArgs {
+    pub path: String,
+    #[serde(default)]
+    pub edits: Vec<SearchReplace>,
+    pub diff: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CodeDeleteArgs {
+    pub path: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CodeMoveArgs {
+    pub from: String,
+    pub to: String,
+}
+
+/// Change made by `code_delete` or `code_move`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileChange {
+    pub action: String,
+    pub from: String,
+    pub to: Option<String>,
+    /// Whether the change was staged with git, false for untracked files
+    pub staged: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CodeTestArgs {
+    /// Test name, path or module to run instead of the whole suite
+    pub filter: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GitLogArgs {
+    pub path: Option<String>,
+    /// Number of commits, defaults to 20
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GitBlameArgs {
+    pub path: String,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GitDiffArgs {
+    /// Revision to compare against, defaults to the base branch of pull requests
+    pub base: Option<String>,
+    pub path: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PullRequestArgs {
-    pub branch_name: String,
     #[serde(deserialize_with = "deserialize_issue_number")]
     pub issue: u64,
     pub title: String,
     pub body: String,
+    /// Message for the changes that were not committed with `git_commit`
+    #[serde(default)]
+    pub commit_message: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GitCommitArgs {
+    pub message: String,
+    /// Files or directories to commit, every file changed by the agent when empty
+    #[serde(default)]
+    pub paths: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatedCommit {
+    pub hash: String,
+    pub subject: String,
+    pub files: Vec<String>,
 }
 
 impl FromStr for Tools {
@@ -94,12 +233,21 @@ impl FromStr for Tools {
             "issue_validate" => Ok(Tools::IssueValidate),
             "issue_pull" => Ok(Tools::IssuePull),
             "pull_request" => Ok(Tools::PullRequest),
+            "git_commit" => Ok(Tools::GitCommit),
             "code_read" => Ok(Tools::CodeRead),
+            "code_search" => Ok(Tools::CodeSearch),
+            "code_list" => Ok(Tools::CodeList),
             "code_analyse" => Ok(Tools::CodeAnalyse),
             "code_lint" => Ok(Tools::CodeLint),
             "code_write" => Ok(Tools::CodeWrite),
+            "code_edit" => Ok(Tools::CodeEdit),
+            "code_delete" => Ok(Tools::CodeDelete),
+            "code_move" => Ok(Tools::CodeMove),
             "code_test" => Ok(Tools::CodeTest),
             "docs_reference" => Ok(Tools::DocsReference),
+            "git_log" => Ok(Tools::GitLog),
+            "git_blame" => Ok(Tools::GitBlame),
+            "git_diff" => Ok(Tools::GitDiff),
             "done" => Ok(Tools::Done),
             _ => Err(CoderError::ConfigError(format!("Invalid tool: {}", s))),
         }
@@ -112,35 +260,113 @@ impl Display for Tools {
             Tools::IssueValidate => "issue_validate",
             Tools::IssuePull => "issue_pull",
             Tools::PullRequest => "pull_request",
+            Tools::GitCommit => "git_commit",
             Tools::CodeRead => "code_read",
+            Tools::CodeSearch => "code_search",
+            Tools::CodeList => "code_list",
             Tools::CodeAnalyse => "code_analyse",
             Tools::CodeLint => "code_lint",
             Tools::CodeWrite => "code_write",
+            Tools::CodeEdit => "code_edit",
+            Tools::CodeDelete => "code_delete",
+            Tools::CodeMove => "code_move",
             Tools::CodeTest => "code_test",
             Tools::DocsReference => "docs_reference",
+            Tools::GitLog => "git_log",
+            Tools::GitBlame => "git_blame",
+            Tools::GitDiff => "git_diff",
             Tools::Done => "done",
         };
         write!(f, "{}", s)
     }
 }
 
-/// Read file content from .coder/index.yaml
+/// Read file content from the working tree
 ///
 /// # Arguments
 ///
+/// * `root` - Repository root the path is resolved against
 /// * `path` - Path to file
+/// * `start_line` - First line to read (1-based), defaults to the first line
+/// * `end_line` - Last line to read (inclusive), defaults to the last line
 ///
 /// # Returns
 ///
-/// * `String` - File content
+/// * `Result<FileContent, CoderError>` - The requested lines, cut at `MAX_READ_BYTES`
 ///
 /// # Example
 ///
 /// ```
-/// let content = code_read("src/main.rs");
+/// let file = code_read(Path::new("."), "src/main.rs", Some(10), Some(20));
 /// ```
-pub fn code_read(path: &str) -> Result<String, CoderError> {
-    let index_path = Path::new(".coder/index.yaml");
+pub fn code_read(
+    root: &Path,
+    path: &str,
+    start_line: Option<usize>,
+    end_line: Option<usize>,
+) -> Result<FileContent, CoderError> {
+    let relative = sandbox::resolve(root, path)?;
+    let file_path = root.join(&relative);
+
+    if !file_path.is_file() {
+        return Err(CoderError::PathError(format!("{} is not a file", path)));
+    }
+    if sandbox::is_ignored(root, &relative) {
+        return Err(CoderError::PathError(format!(
+            "{} is ignored by .gitignore",
+            path
+        )));
+    }
+
+    let content = std::fs::read_to_string(&file_path)?;
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let total_lines = lines.len();
+
+    let start = start_line.unwrap_or(1).max(1);
+    let end = end_line.unwrap_or(total_lines).min(total_lines);
+    if total_lines > 0 && (start > total_lines || start > end) {
+        return Err(CoderError::InvalidArguments(format!(
+            "Line range {}-{} is outside of {} which has {} lines",
+            start, end, path, total_lines
+        )));
+    }
+
+    let mut selected = String::new();
+    let mut last_line = start.saturating_sub(1);
+    let mut truncated = false;
+    for line in lines.iter().take(end).skip(start - 1) {
+        if selected.len() + line.len() > MAX_READ_BYTES {
+            truncated = true;
+            break;
+        }
+        selected.push_str(line);
+        last_line += 1;
+    }
+
+    Ok(FileContent {
+        path: relative.to_string_lossy().to_string(),
+        content: selected,
+        start_line: start,
+        end_line: last_line,
+        total_lines,
+        truncated,
+    })
+}
+
+/// Read file content from .coder/index.yaml
+///
+/// # Arguments
+///
+/// * `root` - Root of the developer's repository, the index is not part of a worktree
+/// * `path` - Path to file
+///
+/// # Returns
+///
+/// * `String` - File content as of the last `coder index`
+pub fn code_read_index(root: &Path, path: &str) -> Result<String, CoderError> {
+    let relative = sandbox::resolve(root, path)?;
+    let path = relative.to_string_lossy();
+    let index_path = root.join(".coder/index.yaml");
 
     if !index_path.exists() {
         return Err(CoderError::ConfigError("Index file not found".to_string()));
@@ -154,134 +380,355 @@ pub fn code_read(path: &str) -> Result<String, CoderError> {
 
     index
         .get("content")
-        .and_then(|c| c.get(path))
+        .and_then(|c| c.get(path.as_ref()))
         .and_then(|v| v.as_str())
         .map(String::from)
         .ok_or_else(|| CoderError::ConfigError(format!("Content not found for path: {}", path)))
 }
 
-/// Create a pull request
+/// Branch the changes for an issue are pushed to, the same on every run so that iterative runs
+/// converge on a single pull request
+pub fn issue_branch(issue: u64) -> String {
+    format!("coder/issue-{}", issue)
+}
+
+/// Create a pull request, or update the open pull request of the issue's branch
+///
+/// The branch is created in the run's worktree, which is removed together with the local branch
+/// once the run ends. Files the agent changed but did not commit with `git_commit` yet are
+/// committed with the given message, nothing else in the tree is staged. An open pull request
+/// receives the new commits, which requires the worktree to start from its branch, and gets the
+/// new title and body. When a step fails the commit is undone, the changes are stashed, the
+/// original branch is checked out again and the remote branch is restored.
 ///
 /// # Arguments
 ///
-/// * `scm_name` - Owner of the repository
-/// * `owner` - Owner of the repository
-/// * `repo` - Name of the repository  
-/// * `branch_name` - Name of the branch
-/// * `issue` - Issue number
-/// * `title` - Title of the pull request
-/// * `body` - Body of the pull request
+/// * `workspace` - Worktree of the run and the files the agent changed in it
+/// * `scm` - SCM provider to open the pull request with
+/// * `scm_config` - Base branch, remote, fork and commit message pattern to use
+/// * `args` - Issue, title and body of the pull request and the commit message
 ///
 /// # Returns
 ///
-/// * `Result<octocrab::models::pulls::PullRequest, CoderError>` - Result of creating the pull request
+/// * `Result<(PullRequest, bool), CoderError>` - The pull request and whether it existed
+///   already, `CoderError::InvalidArguments` when the commit message is missing or does not
+///   match the pattern, `CoderError::GitError` with the output of git when a git step failed
 pub async fn pull_request(
-    scm_name: &str,
-    owner: &str,
-    repo: &str,
-    branch_name: &str,
-    issue: u64,
-    title: &str,
-    body: &str,
-) -> Result<octocrab::models::pulls::PullRequest, CoderError> {
-    if scm_name == "github" {
-        info!(
+    workspace: &Workspace,
+    scm: &dyn ScmProvider,
+    scm_config: &config::ScmConfig,
+    args: &PullRequestArgs,
+) -> Result<(PullRequest, bool), CoderError> {
+    let root = workspace.root();
+    let issue = args.issue;
+    let branch = issue_branch(issue);
+    let branch_name = branch.as_str();
+
+    let pending = workspace.touched();
+    let message = match &args.commit_message {
+        Some(message) => {
+            validate_commit_message(&scm_config.commit_pattern, message)?;
+            Some(message.as_str())
+        }
+        None if !pending.is_empty() => {
+            return Err(CoderError::InvalidArguments(format!(
+                "commit_message is required, these files are not committed yet: {}",
+                display_paths(&pending)
+            )))
+        }
+        None => None,
+    };
+
+    let base = git::base_branch(root, scm_config).await?;
+    let remote = scm_config.push_remote();
+    let head = scm_config.head(branch_name);
+    let existing = scm.find_pull_request(&head).await?;
+    // Restored when the pull request cannot be updated after the push
+    let previous_commit = match &existing {
+        Some(_) => git::remote_branch(root, remote, branch_name).await?,
+        None => None,
+    };
+    match &existing {
+        Some(pr) => info!(
+            "Updating PR #{} for issue #{} on branch {} with title: {}",
+            pr.number, issue, branch_name, args.title
+        ),
+        None => info!(
             "Creating PR for issue #{} on branch {} with title: {}",
-            issue, branch_name, title
-        );
-    } else {
-        info!(
-            "Creating a MR for issue #{} on branch {} with title: {}",
-            issue, branch_name, title
-        );
+            issue, branch_name, args.title
+        ),
     }
 
-    let github_token = std::env::var("CODER_SCM_TOKEN")
-        .map_err(|_| CoderError::ConfigError("CODER_SCM_TOKEN not set".to_string()))?;
+    let original_commit = git::run(root, &["rev-parse", "HEAD"])
+        .await?
+        .trim()
+        .to_string();
+    let original_branch = git::run(root, &["branch", "--show-current"])
+        .await?
+        .trim()
+        .to_string();
+
+    let mut created = false;
+    let mut pushed = false;
+    let result = async {
+        git::run(root, &["checkout", "-q", "-B", branch_name]).await?;
+        created = true;
+        if let Some(message) = message {
+            commit_paths(root, message, &pending).await?;
+        }
+        git::run(root, &["push", "-q", remote, branch_name]).await?;
+        pushed = true;
+        match &existing {
+            Some(pr) => {
+                scm.update_pull_request(pr.number, Some(&args.title), Some(&args.body))
+                    .await
+            }
+            None => {
+                scm.create_pull_request(&head, &base, &args.title, &args.body)
+                    .await
+            }
+        }
+    }
+    .await;
+
+    let pr = match result {
+        Ok(pr) => pr,
+        Err(e) => {
+            warn!("Failed to publish PR for issue #{}: {}", issue, e);
+            if pushed {
+                if let Err(e) =
+                    restore_remote_branch(root, remote, branch_name, previous_commit).await
+                {
+                    warn!("Failed to restore remote branch {}: {}", branch_name, e);
+                }
+            }
+            rollback(
+                root,
+                &original_commit,
+                &original_branch,
+                created.then_some(branch_name),
+                issue,
+            )
+            .await;
+            return Err(e);
+        }
+    };
 
-    let octocrab = Octocrab::builder()
-        .personal_token(github_token)
-        .build()
-        .map_err(CoderError::GitHubError)?;
+    workspace.committed(&pending);
+    info!("Published PR: {}", pr.url.clone().unwrap_or_default());
+    Ok((pr, existing.is_some()))
+}
 
-    Command::new("git")
-        .args(["checkout", "-b", branch_name])
-        .output()
-        .map_err(|e| CoderError::GitError(e.to_string()))?;
+/// Undo the push of a failed pull request
+async fn restore_remote_branch(
+    root: &Path,
+    remote: &str,
+    branch: &str,
+    previous_commit: Option<String>,
+) -> Result<(), CoderError> {
+    match previous_commit {
+        // The open pull request goes back to the commits it had, unless someone pushed since
+        Some(previous) => {
+            let pushed = git::run(root, &["rev-parse", "HEAD"]).await?;
+            let lease = format!("--force-with-lease=refs/heads/{}:{}", branch, pushed.trim());
+            let refspec = format!("{}:refs/heads/{}", previous, branch);
+            git::run(root, &["push", "-q", &lease, remote, &refspec]).await?;
+        }
+        // Nothing will be reviewed on the pushed branch
+        None => {
+            git::run(root, &["push", "-q", remote, "--delete", branch]).await?;
+        }
+    }
+    Ok(())
+}
 
-    Command::new("git")
-        .args(["add", "."])
-        .output()
-        .map_err(|e| CoderError::GitError(e.to_string()))?;
+/// Restore the checkout after a failed pull request, keeping the changes in a stash
+///
+/// Every step is attempted even if a previous one failed, failures are only logged since the
+/// error of the pull request is the one reported.
+async fn rollback(
+    root: &Path,
+    original_commit: &str,
+    original_branch: &str,
+    created_branch: Option<&str>,
+    issue: u64,
+) {
+    let message = format!("coder: changes for issue #{}", issue);
+    let original = if original_branch.is_empty() {
+        original_commit
+    } else {
+        original_branch
+    };
+    let mut steps: Vec<Vec<&str>> = vec![
+        // Undo the commit, its changes stay in the index
+        vec!["reset", "-q", "--soft", original_commit],
+        vec!["stash", "push", "-q", "--include-untracked", "-m", &message],
+        vec!["checkout", "-q", original],
+    ];
+    if let Some(branch) = created_branch {
+        steps.push(vec!["branch", "-D", branch]);
+    }
 
-    Command::new("git")
-        .args(["commit", "-m", &format!("fix: address issue #{}", issue)])
-        .output()
-        .map_err(|e| CoderError::GitError(e.to_string()))?;
+    for step in steps {
+        if let Err(e) = git::run(root, &step).await {
+            warn!("Rollback step failed: {}", e);
+        }
+    }
+    info!(
+        "Rolled back to {}, changes are stashed as '{}'",
+        original, message
+    );
+}
 
-    Command::new("git")
-        .args(["push", "origin", branch_name])
-        .output()
-        .map_err(|e| CoderError::GitError(e.to_string()))?;
+/// Check the subject line of a commit message against the configured pattern
+fn validate_commit_message(pattern: &str, message: &str) -> Result<(), CoderError> {
+    let regex = Regex::new(pattern)
+        .map_err(|e| CoderError::ConfigError(format!("Invalid scm.commit_pattern: {}", e)))?;
+    let subject = message.lines().next().unwrap_or_default().trim();
+    if !regex.is_match(subject) {
+        return Err(CoderError::InvalidArguments(format!(
+            "Commit message '{}' does not match the pattern {}",
+            subject, pattern
+        )));
+    }
+    Ok(())
+}
 
-    let pr = octocrab
-        .pulls(owner, repo)
-        .create(title, branch_name, "main")
-        .body(body)
-        .send()
-        .await
-        .map_err(CoderError::GitHubError)?;
+fn display_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
 
-    Command::new("git")
-        .args(["checkout", "main"])
-        .output()
-        .map_err(|e| CoderError::GitError(e.to_string()))?;
+/// Commit the changes to the given paths, changes staged for other paths are left alone
+///
+/// # Arguments
+///
+/// * `root` - Repository root the paths are relative to
+/// * `message` - Commit message
+/// * `paths` - Files or directories to commit, they may have been deleted or moved away
+///
+/// # Returns
+///
+/// * `Result<Option<CreatedCommit>, CoderError>` - The commit, `None` when the paths have no
+///   changes
+async fn commit_paths(
+    root: &Path,
+    message: &str,
+    paths: &[PathBuf],
+) -> Result<Option<CreatedCommit>, CoderError> {
+    let paths: Vec<String> = paths
+        .iter()
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+
+    // New files have to be known to git before they can be committed
+    let existing: Vec<&str> = paths
+        .iter()
+        .filter(|path| root.join(path).exists() && !sandbox::is_ignored(root, Path::new(path)))
+        .map(String::as_str)
+        .collect();
+    if !existing.is_empty() {
+        let mut args = vec!["add", "-A", "--"];
+        args.extend(existing);
+        git::run(root, &args).await?;
+    }
 
-    Command::new("git")
-        .args(["branch", "-D", branch_name])
-        .output()
-        .map_err(|e| CoderError::GitError(e.to_string()))?;
+    // Renames are listed as a deletion and an addition so both sides are committed
+    let mut args = vec!["diff", "--name-only", "--no-renames", "-z", "HEAD", "--"];
+    args.extend(paths.iter().map(String::as_str));
+    let files: Vec<String> = git::run(root, &args)
+        .await?
+        .split('\0')
+        .filter(|file| !file.is_empty())
+        .map(str::to_string)
+        .collect();
+    if files.is_empty() {
+        return Ok(None);
+    }
 
-    info!("Created PR: {}", pr.clone().html_url.unwrap());
-    Ok(pr)
+    let mut args = vec!["commit", "-q", "-m", message, "--only", "--"];
+    args.extend(files.iter().map(String::as_str));
+    git::run(root, &args).await?;
+
+    let hash = git::run(root, &["rev-parse", "--short", "HEAD"]).await?;
+    info!("Committed {} files as {}", files.len(), hash.trim());
+    Ok(Some(CreatedCommit {
+        hash: hash.trim().to_string(),
+        subject: message.lines().next().unwrap_or_default().to_string(),
+        files,
+    }))
 }
 
-/// Pull issue from SCN
+/// Commit files the agent changed, one call per logical change
 ///
 /// # Arguments
 ///
-/// * `scm_name` - The name of the SCM (e.g. github, gitlab)
-/// * `issue_number` - Issue number
-/// * `owner` - Owner of the repository
-/// * `repo` - Name of the repository
+/// * `workspace` - Worktree of the run and the files the agent changed in it
+/// * `pattern` - Regex the subject line of the message has to match
+/// * `message` - Commit message
+/// * `paths` - Files or directories to commit, every changed file when empty
 ///
 /// # Returns
 ///
-/// * `Result<octocrab::models::issues::Issue, CoderError>` - Result of pulling the issue
-pub async fn issue_pull(
-    scm_name: &str,
-    issue_number: u64,
-    owner: &str,
-    repo: &str,
-) -> Result<octocrab::models::issues::Issue, CoderError> {
-    if scm_name == "github" {
-        info!("Pulling issue #{} from GitHub", issue_number);
-    } else {
-        info!("Pulling MR #{} from GitLab", issue_number);
+/// * `Result<CreatedCommit, CoderError>` - The commit, `CoderError::InvalidArguments` when the
+///   message does not match the pattern, a path was not changed by the agent or there is
+///   nothing to commit
+pub async fn git_commit(
+    workspace: &Workspace,
+    pattern: &str,
+    message: &str,
+    paths: &[String],
+) -> Result<CreatedCommit, CoderError> {
+    validate_commit_message(pattern, message)?;
+
+    let touched = workspace.touched();
+    let mut selected = Vec::new();
+    if paths.is_empty() {
+        selected.clone_from(&touched);
     }
+    for path in paths {
+        let path = sandbox::resolve(workspace.root(), path)?;
+        let matching: Vec<PathBuf> = touched
+            .iter()
+            .filter(|touched| touched.starts_with(&path))
+            .cloned()
+            .collect();
+        if matching.is_empty() {
+            return Err(CoderError::InvalidArguments(format!(
+                "{} was not changed by you, uncommitted changes: {}",
+                path.display(),
+                display_paths(&touched)
+            )));
+        }
+        selected.extend(matching);
+    }
+    selected.sort();
+    selected.dedup();
 
-    let octocrab = Octocrab::builder()
-        .personal_token(std::env::var("CODER_SCM_TOKEN").unwrap())
-        .build()
-        .map_err(CoderError::GitHubError)?;
-
-    let issue = octocrab
-        .issues(owner, repo)
-        .get(issue_number)
-        .await
-        .map_err(CoderError::GitHubError)?;
+    let commit = commit_paths(workspace.root(), message, &selected)
+        .await?
+        .ok_or_else(|| {
+            CoderError::InvalidArguments("There are no changes to commit".to_string())
+        })?;
+    workspace.committed(&selected);
+    Ok(commit)
+}
 
-    Ok(issue)
+/// Pull issue from SCM
+///
+/// # Arguments
+///
+/// * `scm` - SCM provider to pull the issue from
+/// * `issue_number` - Issue number
+///
+/// # Returns
+///
+/// * `Result<Issue, CoderError>` - Result of pulling the issue
+pub async fn issue_pull(scm: &dyn ScmProvider, issue_number: u64) -> Result<Issue, CoderError> {
+    scm.get_issue(issue_number).await
 }
 
 pub fn issue_validate(
@@ -333,37 +780,384 @@ pub fn issue_validate(
     Ok(())
 }
 
+/// Search the repository for a literal text or regex, skipping gitignored files
+///
+/// # Arguments
+///
+/// * `root` - Repository root the path is resolved against
+/// * `args` - Query, optional path and context lines
+///
+/// # Returns
+///
+/// * `Result<search::SearchResults, CoderError>` - Matching lines with context, capped at `search::MAX_SEARCH_RESULTS`
+pub fn code_search(
+    root: &Path,
+    args: &CodeSearchArgs,
+) -> Result<search::SearchResults, CoderError> {
+    let matcher = search::matcher(&args.query, args.regex, args.ignore_case)?;
+    let path = match &args.path {
+        Some(path) => sandbox::resolve(root, path)?,
+        None => PathBuf::new(),
+    };
+    if !root.join(&path).exists() {
+        return Err(CoderError::PathError(format!(
+            "{} does not exist",
+            path.display()
+        )));
+    }
+
+    Ok(search::search(
+        root,
+        &path,
+        &matcher,
+        args.context.unwrap_or(2),
+    ))
+}
+
+/// List files and directories with their types and sizes, skipping gitignored files
+///
+/// # Arguments
+///
+/// * `root` - Repository root the path is resolved against
+/// * `args` - Optional path, depth and glob filter
+///
+/// # Returns
+///
+/// * `Result<index::Listing, CoderError>` - The entries, capped at `index::MAX_LIST_ENTRIES`
+pub fn code_list(root: &Path, args: &CodeListArgs) -> Result<index::Listing, CoderError> {
+    let path = match &args.path {
+        Some(path) if !path.trim().is_empty() && path.trim() != "." => {
+            sandbox::resolve(root, path)?
+        }
+        _ => PathBuf::new(),
+    };
+    if !root.join(&path).exists() {
+        return Err(CoderError::PathError(format!(
+            "{} does not exist",
+            path.display()
+        )));
+    }
+
+    let glob = match &args.glob {
+        Some(glob) => Some(
+            globset::Glob::new(glob)
+                .map_err(|e| CoderError::InvalidArguments(format!("Invalid glob: {}", e)))?
+                .compile_matcher(),
+        ),
+        None => None,
+    };
+
+    Ok(index::list(
+        root,
+        &path,
+        args.depth.unwrap_or(1).max(1),
+        glob.as_ref(),
+    )?)
+}
+
 /// Write file content
 ///
 /// # Arguments
 ///
+/// * `root` - Repository root the path is resolved against
+/// * `scope` - Files the agent is allowed to change
 /// * `path` - Path to file
 /// * `content` - Content to write
 ///
 /// # Returns
 ///
-/// * `Result<(), CoderError>` - Result of writing the file content
-pub fn code_write(path: &str, content: &str) -> Result<(), CoderError> {
-    let file_path = Path::new(path);
+/// * `Result<PathBuf, CoderError>` - The written path relative to the root, `CoderError::PathError`
+///   when the path leaves the repository or targets `.git`/`.coder`, `CoderError::OutOfScope` when
+///   the path is not allowed
+pub fn code_write(
+    root: &Path,
+    scope: &Scope,
+    path: &str,
+    content: &str,
+) -> Result<PathBuf, CoderError> {
+    let relative = writable_path(root, scope, path)?;
+
+    let file_path = root.join(&relative);
     if let Some(parent) = file_path.parent() {
         std::fs::create_dir_all(parent)?;
     }
     std::fs::write(file_path, content)?;
-    Ok(())
+    Ok(relative)
+}
+
+/// Resolve a path the agent wants to change, checking the sandbox and the change scope
+fn writable_path(root: &Path, scope: &Scope, path: &str) -> Result<PathBuf, CoderError> {
+    let resolved = sandbox::resolve_writable(root, path).inspect_err(|e| warn!("{}", e))?;
+    // A symbolic link must not lead out of the scope either
+    if !scope.is_allowed(&resolved.path.to_string_lossy())
+        || !scope.is_allowed(&resolved.target.to_string_lossy())
+    {
+        warn!("Refusing to write outside of the change scope: {}", path);
+        return Err(CoderError::OutOfScope(format!(
+            "{} is outside of the change scope. {}",
+            path,
+            scope.describe()
+        )));
+    }
+    Ok(resolved.path)
+}
+
+/// Edit an existing file with search/replace blocks or a unified diff
+///
+/// The edits are applied in memory and the file is only replaced when all of them
+/// succeed, so a failed edit leaves the file untouched.
+///
+/// # Arguments
+///
+/// * `root` - Repository root the path is resolved against
+/// * `scope` - Files the agent is allowed to change
+/// * `path` - Path to file
+/// * `edits` - Search/replace blocks, mutually exclusive with `diff`
+/// * `diff` - Unified diff, mutually exclusive with `edits`
+///
+/// # Returns
+///
+/// * `Result<PathBuf, CoderError>` - The edited path relative to the root, `CoderError::EditError`
+///   naming the block or hunk that did not apply
+pub fn code_edit(
+    root: &Path,
+    scope: &Scope,
+    path: &str,
+    edits: &[SearchReplace],
+    diff: Option<&str>,
+) -> Result<PathBuf, CoderError> {
+    let relative = writable_path(root, scope, path)?;
+    let file_path = root.join(&relative);
+    if !file_path.is_file() {
+        return Err(CoderError::PathError(format!(
+            "{} is not a file, use code_write to create it",
+            path
+        )));
+    }
+
+    let content = std::fs::read_to_string(&file_path)?;
+    let edited = match (edits.is_empty(), diff) {
+        (false, None) => edit::apply_search_replace(&content, edits)?,
+        (true, Some(diff)) => edit::apply_unified_diff(&content, diff)?,
+        _ => {
+            return Err(CoderError::InvalidArguments(
+                "Provide either edits or diff".to_string(),
+            ))
+        }
+    };
+
+    // Write next to the file and rename, so the file is never left half written
+    let tmp_path = file_path.with_file_name(format!(
+        ".{}.coder-edit",
+        file_path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    std::fs::write(&tmp_path, edited)?;
+    std::fs::rename(&tmp_path, &file_path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&tmp_path);
+    })?;
+
+    info!("Edited {}", relative.display());
+    Ok(relative)
+}
+
+/// Resolve a path to change, checking every file below it when it is a directory
+fn writable_tree(root: &Path, scope: &Scope, path: &str) -> Result<PathBuf, CoderError> {
+    let relative = writable_path(root, scope, path)?;
+    let absolute = root.join(&relative);
+    if absolute.is_dir() {
+        for entry in index::walk_builder(&absolute)
+            .hidden(false)
+            .build()
+            .flatten()
+        {
+            if let Ok(nested) = entry.path().strip_prefix(root) {
+                writable_path(root, scope, &nested.to_string_lossy())?;
+            }
+        }
+    }
+    Ok(relative)
+}
+
+/// Delete a file or directory, staging the removal when git tracks it
+///
+/// # Arguments
+///
+/// * `root` - Repository root the path is resolved against
+/// * `scope` - Files the agent is allowed to change
+/// * `path` - File or directory to delete
+///
+/// # Returns
+///
+/// * `Result<FileChange, CoderError>` - The deleted path and whether the removal was staged
+pub async fn code_delete(root: &Path, scope: &Scope, path: &str) -> Result<FileChange, CoderError> {
+    let relative = writable_tree(root, scope, path)?;
+    let absolute = root.join(&relative);
+    if !absolute.exists() {
+        return Err(CoderError::PathError(format!("{} does not exist", path)));
+    }
+
+    let staged = git::is_tracked(root, &relative).await?;
+    if staged {
+        git::run(
+            root,
+            &["rm", "-r", "-q", "-f", "--", &relative.to_string_lossy()],
+        )
+        .await?;
+    }
+    // Untracked files are left behind by git rm, local changes are discarded with -f
+    if absolute.is_dir() {
+        std::fs::remove_dir_all(&absolute)?;
+    } else if absolute.exists() {
+        std::fs::remove_file(&absolute)?;
+    }
+
+    info!("Deleted {}", relative.display());
+    Ok(FileChange {
+        action: "deleted".to_string(),
+        from: relative.to_string_lossy().to_string(),
+        to: None,
+        staged,
+    })
+}
+
+/// Move or rename a file or directory, staging the rename when git tracks it
+///
+/// # Arguments
+///
+/// * `root` - Repository root the paths are resolved against
+/// * `scope` - Files the agent is allowed to change, applies to both paths
+/// * `from` - Existing file or directory
+/// * `to` - New path, which must not exist yet
+///
+/// # Returns
+///
+/// * `Result<FileChange, CoderError>` - The moved paths and whether the rename was staged
+pub async fn code_move(
+    root: &Path,
+    scope: &Scope,
+    from: &str,
+    to: &str,
+) -> Result<FileChange, CoderError> {
+    let source = writable_tree(root, scope, from)?;
+    let target = writable_path(root, scope, to)?;
+    if !root.join(&source).exists() {
+        return Err(CoderError::PathError(format!("{} does not exist", from)));
+    }
+    if root.join(&target).exists() {
+        return Err(CoderError::PathError(format!("{} already exists", to)));
+    }
+    if let Some(parent) = root.join(&target).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let staged = git::is_tracked(root, &source).await?;
+    if staged {
+        git::run(
+            root,
+            &[
+                "mv",
+                "--",
+                &source.to_string_lossy(),
+                &target.to_string_lossy(),
+            ],
+        )
+        .await?;
+    } else {
+        std::fs::rename(root.join(&source), root.join(&target))?;
+    }
+
+    info!("Moved {} to {}", source.display(), target.display());
+    Ok(FileChange {
+        action: "moved".to_string(),
+        from: source.to_string_lossy().to_string(),
+        to: Some(target.to_string_lossy().to_string()),
+        staged,
+    })
+}
+
+/// Resolve an optional tool path, `None` meaning the whole repository
+fn optional_path(root: &Path, path: Option<&str>) -> Result<Option<PathBuf>, CoderError> {
+    match path {
+        Some(path) if !path.trim().is_empty() && path.trim() != "." => {
+            Ok(Some(sandbox::resolve(root, path)?))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Run one of the read-only git history tools
+///
+/// # Arguments
+///
+/// * `root` - Repository root
+/// * `tool` - `Tools::GitLog`, `Tools::GitBlame` or `Tools::GitDiff`
+/// * `args` - Arguments of the tool
+/// * `scm` - SCM config with the base branch `git_diff` compares against by default
+///
+/// # Returns
+///
+/// * `Result<Value, CoderError>` - Commits, blamed lines or the diff as JSON
+pub async fn git_history(
+    root: &Path,
+    tool: &Tools,
+    args: Value,
+    scm: &config::ScmConfig,
+) -> Result<Value, CoderError> {
+    match tool {
+        Tools::GitLog => {
+            let args: GitLogArgs = serde_json::from_value(args)?;
+            let path = optional_path(root, args.path.as_deref())?;
+            let commits = git::log(root, path.as_deref(), args.limit.unwrap_or(20)).await?;
+            Ok(serde_json::to_value(commits)?)
+        }
+        Tools::GitBlame => {
+            let args: GitBlameArgs = serde_json::from_value(args)?;
+            if args.start_line == 0 || args.start_line > args.end_line {
+                return Err(CoderError::InvalidArguments(format!(
+                    "Invalid line range {}-{}",
+                    args.start_line, args.end_line
+                )));
+            }
+            let path = sandbox::resolve(root, &args.path)?;
+            let lines = git::blame(root, &path, args.start_line, args.end_line).await?;
+            Ok(serde_json::to_value(lines)?)
+        }
+        Tools::GitDiff => {
+            let args: GitDiffArgs = serde_json::from_value(args)?;
+            let path = optional_path(root, args.path.as_deref())?;
+            let base = match args.base {
+                Some(base) => base,
+                None => git::base_branch(root, scm).await?,
+            };
+            Ok(serde_json::to_value(
+                git::diff(root, &base, path.as_deref()).await?,
+            )?)
+        }
+        _ => Err(CoderError::ConfigError(format!(
+            "{} is not a git history tool",
+            tool
+        ))),
+    }
 }
 
 /// Get documentation references
 ///
 /// # Arguments
 ///
+/// * `root` - Developer's repository the docs paths are relative to
+/// * `language` - Language config with the docs sources
 /// * `term` - Term to get documentation references for
 ///
 /// # Returns
 ///
-/// * `Result<(), CoderError>` - Result of getting documentation references
-pub async fn docs_reference(term: &str) -> Result<(), CoderError> {
+/// * `Result<Vec<docs::Snippet>, CoderError>` - Ranked snippets from the local docs and the docs URL
+pub async fn docs_reference(
+    root: &Path,
+    language: &config::LanguageConfig,
+    term: &str,
+) -> Result<Vec<docs::Snippet>, CoderError> {
     info!("Getting documentation references for the term: {}", term);
-    Ok(())
+    docs::lookup(root, language, term).await
 }
 
 // /// Update project status
@@ -476,10 +1270,15 @@ pub fn get_tools() -> Vec<Tool> {
             r#type: ToolType::Function,
             function: FunctionObject {
                 name: Tools::CodeTest.to_string(),
-                description: "Test the code".to_string(),
+                description: "Test the code, optionally only the tests matching a filter".to_string(),
                 parameters: json!({
                     "type": "object",
-                    "properties": {},
+                    "properties": {
+                        "filter": {
+                            "type": "string",
+                            "description": "Test name, path or module to run instead of the whole test suite"
+                        }
+                    },
                     "required": []
                 }),
             },
@@ -488,7 +1287,7 @@ pub fn get_tools() -> Vec<Tool> {
             r#type: ToolType::Function,
             function: FunctionObject {
                 name: Tools::DocsReference.to_string(),
-                description: "Reference the docs".to_string(),
+                description: "Look a term up in the project documentation and the language docs, returns ranked snippets".to_string(),
                 parameters: json!({
                     "type": "object",
                     "properties": {
@@ -505,13 +1304,25 @@ pub fn get_tools() -> Vec<Tool> {
             r#type: ToolType::Function,
             function: FunctionObject {
                 name: Tools::CodeRead.to_string(),
-                description: "Read a code content".to_string(),
+                description: "Read a file from the working tree".to_string(),
                 parameters: json!({
                     "type": "object",
                     "properties": {
                         "path": {
                             "type": "string",
                             "description": "The path to the file"
+                        },
+                        "start_line": {
+                            "type": "number",
+                            "description": "First line to read (1-based), defaults to the start of the file"
+                        },
+                        "end_line": {
+                            "type": "number",
+                            "description": "Last line to read (inclusive), defaults to the end of the file"
+                        },
+                        "from_index": {
+                            "type": "boolean",
+                            "description": "Read the indexed snapshot instead of the working tree"
                         }
                     },
                     "required": ["path"]
@@ -521,18 +1332,76 @@ pub fn get_tools() -> Vec<Tool> {
         Tool {
             r#type: ToolType::Function,
             function: FunctionObject {
-                name: Tools::CodeWrite.to_string(),
-                description: "Write code to a file".to_string(),
+                name: Tools::CodeSearch.to_string(),
+                description: "Search file contents for a text or regex, returns file:line matches with context".to_string(),
                 parameters: json!({
                     "type": "object",
                     "properties": {
-                        "path": {
+                        "query": {
                             "type": "string",
-                            "description": "The path to the file"
+                            "description": "The text to search for"
                         },
-                        "content": {
+                        "regex": {
+                            "type": "boolean",
+                            "description": "Treat the query as a regular expression"
+                        },
+                        "ignore_case": {
+                            "type": "boolean",
+                            "description": "Match case insensitively"
+                        },
+                        "path": {
                             "type": "string",
-                            "description": "The content to write"
+                            "description": "File or directory to search, defaults to the whole repository"
+                        },
+                        "context": {
+                            "type": "number",
+                            "description": "Lines of context around each match, defaults to 2"
+                        }
+                    },
+                    "required": ["query"]
+                }),
+            },
+        },
+        Tool {
+            r#type: ToolType::Function,
+            function: FunctionObject {
+                name: Tools::CodeList.to_string(),
+                description: "List files and directories with their type and size, also tells whether a file exists".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "File or directory to list, defaults to the repository root"
+                        },
+                        "depth": {
+                            "type": "number",
+                            "description": "Directory levels to descend, defaults to 1"
+                        },
+                        "glob": {
+                            "type": "string",
+                            "description": "Only include entries matching the glob (e.g. *.rs)"
+                        }
+                    },
+                    "required": []
+                }),
+            },
+        },
+        Tool {
+            r#type: ToolType::Function,
+            function: FunctionObject {
+                name: Tools::CodeWrite.to_string(),
+                description: "Write code to a file".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "The path to the file"
+                        },
+                        "content": {
+                            "type": "string",
+                            "description": "The content to write"
                         }
                     },
                     "required": ["path", "content"]
@@ -542,15 +1411,88 @@ pub fn get_tools() -> Vec<Tool> {
         Tool {
             r#type: ToolType::Function,
             function: FunctionObject {
-                name: Tools::PullRequest.to_string(),
-                description: "Create a Pull Request".to_string(),
+                name: Tools::CodeEdit.to_string(),
+                description: "Edit an existing file with search/replace blocks or a unified diff, nothing is changed unless every block applies".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "The path to the file"
+                        },
+                        "edits": {
+                            "type": "array",
+                            "description": "Blocks applied in order, each search text must appear exactly once in the file",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "search": {
+                                        "type": "string",
+                                        "description": "The exact text to replace"
+                                    },
+                                    "replace": {
+                                        "type": "string",
+                                        "description": "The replacement text"
+                                    }
+                                },
+                                "required": ["search", "replace"]
+                            }
+                        },
+                        "diff": {
+                            "type": "string",
+                            "description": "A unified diff of the file, used instead of edits"
+                        }
+                    },
+                    "required": ["path"]
+                }),
+            },
+        },
+        Tool {
+            r#type: ToolType::Function,
+            function: FunctionObject {
+                name: Tools::CodeDelete.to_string(),
+                description: "Delete a file or directory, the removal is staged with git".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "The path to delete"
+                        }
+                    },
+                    "required": ["path"]
+                }),
+            },
+        },
+        Tool {
+            r#type: ToolType::Function,
+            function: FunctionObject {
+                name: Tools::CodeMove.to_string(),
+                description: "Move or rename a file or directory, the rename is staged with git".to_string(),
                 parameters: json!({
                     "type": "object",
                     "properties": {
-                        "branch_name": {
+                        "from": {
                             "type": "string",
-                            "description": "The branch name"
+                            "description": "The existing path"
                         },
+                        "to": {
+                            "type": "string",
+                            "description": "The new path, it must not exist yet"
+                        }
+                    },
+                    "required": ["from", "to"]
+                }),
+            },
+        },
+        Tool {
+            r#type: ToolType::Function,
+            function: FunctionObject {
+                name: Tools::PullRequest.to_string(),
+                description: "Create a Pull Request for the issue, or update the one that is already open".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
                         "issue": {
                             "type": "number",
                             "description": "The issue number"
@@ -563,9 +1505,101 @@ pub fn get_tools() -> Vec<Tool> {
                             "type": "string",
                             "description": "The pull request body"
                         },
-
+                        "commit_message": {
+                            "type": "string",
+                            "description": "Conventional commit message (e.g. 'fix(parser): handle empty input') for the changes not committed with git_commit yet"
+                        },
+                    },
+                    "required": ["issue", "title", "body"]
+                }),
+            },
+        },
+        Tool {
+            r#type: ToolType::Function,
+            function: FunctionObject {
+                name: Tools::GitCommit.to_string(),
+                description: "Commit files you changed as one logical change, call it once per change before creating the pull request".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "message": {
+                            "type": "string",
+                            "description": "Conventional commit message, e.g. 'fix(parser): handle empty input' followed by an optional body"
+                        },
+                        "paths": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Files or directories to commit, defaults to every file you changed and did not commit yet"
+                        }
+                    },
+                    "required": ["message"]
+                }),
+            },
+        },
+        Tool {
+            r#type: ToolType::Function,
+            function: FunctionObject {
+                name: Tools::GitLog.to_string(),
+                description: "List recent commits, optionally only the ones touching a path".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "File or directory to show the history of"
+                        },
+                        "limit": {
+                            "type": "number",
+                            "description": "Number of commits, defaults to 20"
+                        }
+                    },
+                    "required": []
+                }),
+            },
+        },
+        Tool {
+            r#type: ToolType::Function,
+            function: FunctionObject {
+                name: Tools::GitBlame.to_string(),
+                description: "Show the commit and author that last changed each line in a range".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "path": {
+                            "type": "string",
+                            "description": "The path to the file"
+                        },
+                        "start_line": {
+                            "type": "number",
+                            "description": "First line (1-based)"
+                        },
+                        "end_line": {
+                            "type": "number",
+                            "description": "Last line (inclusive)"
+                        }
+                    },
+                    "required": ["path", "start_line", "end_line"]
+                }),
+            },
+        },
+        Tool {
+            r#type: ToolType::Function,
+            function: FunctionObject {
+                name: Tools::GitDiff.to_string(),
+                description: "Show the diff of the working tree, including uncommitted changes and new files, against a base revision".to_string(),
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "base": {
+                            "type": "string",
+                            "description": "Revision to compare against, defaults to the branch pull requests target"
+                        },
+                        "path": {
+                            "type": "string",
+                            "description": "Only show changes of this file or directory"
+                        }
                     },
-                    "required": ["branch_name", "issue", "title", "body"]
+                    "required": []
                 }),
             },
         },
@@ -592,16 +1626,59 @@ pub struct StatusResponse {
     pub retry: bool,
 }
 
+impl StatusResponse {
+    /// Retryable error for mistakes the model can correct, e.g. a path outside of the repository
+    pub fn rejected(message: String) -> Self {
+        Self {
+            status: "error".to_string(),
+            message: Some(message),
+            result: None,
+            retry: true,
+        }
+    }
+}
+
+/// Maximum number of bytes of stdout and of stderr returned for a command
+pub const MAX_COMMAND_OUTPUT_BYTES: usize = 16 * 1024;
+
+/// Maximum number of bytes of stdout and of stderr kept in memory and parsed for diagnostics
+const MAX_CAPTURED_OUTPUT_BYTES: usize = 1024 * 1024;
+
+/// Maximum number of diagnostics returned for a command, errors are kept before warnings
+pub const MAX_COMMAND_DIAGNOSTICS: usize = 50;
+
 /// Execute a language-specific command from config
+///
+/// The command is run by the language shell in the repository root or its configured `cwd`,
+/// and killed with everything it started after its timeout (`command_timeout` by default).
+/// A command that runs but exits unsuccessfully or times out returns a `failed` status with
+/// its output, only failing to start it is an error.
+///
+/// # Arguments
+///
+/// * `root` - Repository root
+/// * `config` - Language config with the commands and shell
+/// * `command_type` - Which command to run
+/// * `filter` - Optional test filter, run with the `test_filter` template instead
+///
+/// # Returns
+///
+/// * `Result<StatusResponse, CoderError>` - Exit code, diagnostics, stdout and stderr
 pub async fn execute_language_specific_command(
+    root: &Path,
     config: &config::LanguageConfig,
     command_type: CommandType,
+    filter: Option<&str>,
 ) -> Result<StatusResponse, CoderError> {
-    let command = match command_type {
-        CommandType::Lint => &config.linter,
-        CommandType::Analyse => &config.analyse,
-        CommandType::Test => &config.test_command,
+    let mut options = match command_type {
+        CommandType::Lint => config.linter.options(),
+        CommandType::Analyse => config.analyse.options(),
+        CommandType::Test => config.test_command.options(),
     };
+    if let Some(filter) = filter.filter(|f| !f.trim().is_empty()) {
+        options.command = filtered_command(config, &command_type, filter)?;
+    }
+    let command = &options.command;
 
     if command.trim().is_empty() {
         return Err(CoderError::ConfigError(format!(
@@ -610,34 +1687,143 @@ pub async fn execute_language_specific_command(
         )));
     }
 
-    let parts: Vec<&str> = command.split_whitespace().collect();
-    if parts.is_empty() {
-        return Err(CoderError::ConfigError("Empty command".to_string()));
-    }
-
-    let output = Command::new(parts[0])
-        .args(&parts[1..])
-        .output()
-        .map_err(|e| CoderError::CommandError(e.to_string()))?;
+    let Some((shell, shell_args)) = config.shell.split_first() else {
+        return Err(CoderError::ConfigError("Empty shell".to_string()));
+    };
+    let cwd = match &options.cwd {
+        Some(cwd) => root.join(sandbox::resolve(root, cwd)?),
+        None => root.to_path_buf(),
+    };
 
-    if !output.status.success() {
-        return Err(CoderError::CommandError(format!(
-            "Command '{}' failed: {}",
-            command,
-            String::from_utf8_lossy(&output.stderr)
-        )));
+    let timeout = options.timeout.unwrap_or(config.command_timeout);
+    let mut process = tokio::process::Command::new(shell);
+    process
+        .args(shell_args)
+        .arg(command)
+        .envs(&options.env)
+        .current_dir(&cwd);
+    let output = process::run(
+        process,
+        Duration::from_secs(timeout),
+        MAX_CAPTURED_OUTPUT_BYTES,
+    )
+    .await
+    .map_err(|e| CoderError::CommandError(format!("Failed to run '{}': {}", command, e)))?;
+
+    // Tools such as cargo clippy report on stderr, so both streams are parsed and returned
+    let mut diagnostics = diagnostics::parse(&format!("{}\n{}", output.stdout, output.stderr));
+    let diagnostics_count = diagnostics.len();
+    diagnostics.sort_by_key(|d| d.severity != diagnostics::Severity::Error);
+    diagnostics.truncate(MAX_COMMAND_DIAGNOSTICS);
+    let (stdout, stdout_truncated) = truncate_output(&output.stdout, MAX_COMMAND_OUTPUT_BYTES);
+    let (stderr, stderr_truncated) = truncate_output(&output.stderr, MAX_COMMAND_OUTPUT_BYTES);
+    let result = json!({
+        "exit_code": output.exit_code,
+        "timed_out": output.timed_out,
+        "diagnostics": diagnostics,
+        "diagnostics_truncated": diagnostics_count > diagnostics.len(),
+        "stdout": stdout,
+        "stderr": stderr,
+        "truncated": output.truncated || stdout_truncated || stderr_truncated,
+    });
+
+    // A failing run is what the model needs to see to fix the code, so it is not an error
+    if !output.success {
+        let reason = match output.exit_code {
+            _ if output.timed_out => format!("timed out after {} seconds", timeout),
+            Some(code) => format!("failed with exit code {}", code),
+            None => "was killed".to_string(),
+        };
+        return Ok(StatusResponse {
+            status: "failed".to_string(),
+            message: Some(format!(
+                "Command '{}' {} and {} diagnostics",
+                command, reason, diagnostics_count
+            )),
+            result: Some(result),
+            retry: false,
+        });
     }
 
     Ok(StatusResponse {
         status: "ok".to_string(),
-        message: Some(format!("Command '{}' succeeded", command)),
-        result: Some(serde_json::Value::String(
-            String::from_utf8_lossy(&output.stdout).to_string(),
+        message: Some(format!(
+            "Command '{}' succeeded with {} diagnostics",
+            command, diagnostics_count
         )),
+        result: Some(result),
         retry: false,
     })
 }
 
+/// Quote a value for `sh`, so a filter cannot inject shell syntax
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Command line running only the tests matching the filter
+fn filtered_command(
+    config: &config::LanguageConfig,
+    command_type: &CommandType,
+    filter: &str,
+) -> Result<String, CoderError> {
+    if !matches!(command_type, CommandType::Test) {
+        return Err(CoderError::InvalidArguments(format!(
+            "A filter is only supported for tests, not for {}",
+            command_type
+        )));
+    }
+    let Some(template) = config.test_filter.as_deref() else {
+        return Err(CoderError::InvalidArguments(format!(
+            "No test filter configured for language {}, run the tests without a filter",
+            config.name
+        )));
+    };
+
+    let filter = shell_quote(filter.trim());
+    if template.contains("{filter}") {
+        Ok(template.replace("{filter}", &filter))
+    } else {
+        Ok(format!("{} {}", template, filter))
+    }
+}
+
+/// Cut long command output, keeping the start and the end where summaries are printed
+///
+/// # Arguments
+///
+/// * `output` - Output of the command
+/// * `max_bytes` - Maximum number of bytes to keep
+///
+/// # Returns
+///
+/// * `(String, bool)` - The output and whether anything was cut
+pub fn truncate_output(output: &str, max_bytes: usize) -> (String, bool) {
+    if output.len() <= max_bytes {
+        return (output.to_string(), false);
+    }
+
+    let mut head = max_bytes / 4;
+    while !output.is_char_boundary(head) {
+        head -= 1;
+    }
+    let mut tail = output.len() - (max_bytes - head);
+    while !output.is_char_boundary(tail) {
+        tail += 1;
+    }
+
+    let omitted = tail - head;
+    (
+        format!(
+            "{}\n... {} bytes omitted ...\n{}",
+            &output[..head],
+            omitted,
+            &output[tail..]
+        ),
+        true,
+    )
+}
+
 #[derive(Debug)]
 pub enum CommandType {
     Analyse,
@@ -658,20 +1844,106 @@ impl Display for CommandType {
 pub async fn handle_tool_calls(
     tool: &Tools,
     args: Option<Value>,
+    workspace: &Workspace,
     config: &config::Config,
+    scm: &dyn ScmProvider,
 ) -> Result<serde_json::Value, CoderError> {
     info!("Handling tool call: {} with args: {:?}", tool, args);
+    let root = workspace.root();
     match tool {
         Tools::CodeRead => {
             let args = args.ok_or_else(|| {
                 CoderError::MissingArguments("CodeRead requires arguments".to_string())
             })?;
             let args: CodeReadArgs = serde_json::from_value(args)?;
-            let content = code_read(&args.path)?;
+            let result = if args.from_index {
+                code_read_index(workspace.repository(), &args.path).map(|content| json!(content))
+            } else {
+                code_read(root, &args.path, args.start_line, args.end_line)
+                    .and_then(|file| Ok(serde_json::to_value(file)?))
+            };
+            let content = match result {
+                Err(e @ (CoderError::PathError(_) | CoderError::InvalidArguments(_))) => {
+                    return Ok(serde_json::to_value(StatusResponse::rejected(
+                        e.to_string(),
+                    ))?)
+                }
+                result => result?,
+            };
+            let message = if content["truncated"] == json!(true) {
+                format!(
+                    "Code read, truncated after line {} of {} - use start_line to read the rest",
+                    content["end_line"], content["total_lines"]
+                )
+            } else {
+                "Code read".to_string()
+            };
+            let response = StatusResponse {
+                status: "ok".to_string(),
+                message: Some(message),
+                result: Some(content),
+                retry: false,
+            };
+            Ok(serde_json::to_value(response)?)
+        }
+        Tools::CodeSearch => {
+            let args = args.ok_or_else(|| {
+                CoderError::MissingArguments("CodeSearch requires arguments".to_string())
+            })?;
+            let args: CodeSearchArgs = serde_json::from_value(args)?;
+            let results = match code_search(root, &args) {
+                Err(e @ (CoderError::PathError(_) | CoderError::InvalidArguments(_))) => {
+                    return Ok(serde_json::to_value(StatusResponse::rejected(
+                        e.to_string(),
+                    ))?)
+                }
+                result => result?,
+            };
+            let message = if results.truncated {
+                format!(
+                    "Found more than {} matches, showing the first ones - narrow the query or path",
+                    search::MAX_SEARCH_RESULTS
+                )
+            } else {
+                format!("Found {} matches", results.matches.len())
+            };
+            let response = StatusResponse {
+                status: "ok".to_string(),
+                message: Some(message),
+                result: Some(serde_json::to_value(results)?),
+                retry: false,
+            };
+            Ok(serde_json::to_value(response)?)
+        }
+        Tools::CodeList => {
+            let args: CodeListArgs = match args {
+                Some(args) => serde_json::from_value(args)?,
+                None => CodeListArgs {
+                    path: None,
+                    depth: None,
+                    glob: None,
+                },
+            };
+            let listing = match code_list(root, &args) {
+                Err(e @ (CoderError::PathError(_) | CoderError::InvalidArguments(_))) => {
+                    return Ok(serde_json::to_value(StatusResponse::rejected(
+                        e.to_string(),
+                    ))?)
+                }
+                result => result?,
+            };
+            let message = if listing.truncated {
+                format!(
+                    "Listed the first {} entries - narrow the path, depth or glob",
+                    index::MAX_LIST_ENTRIES
+                )
+            } else {
+                format!("Listed {} entries", listing.entries.len())
+            };
             let response = StatusResponse {
                 status: "ok".to_string(),
-                message: Some("Code read".to_string()),
-                result: Some(serde_json::to_value(content)?),
+                message: Some(message),
+                result: Some(serde_json::to_value(listing)?),
                 retry: false,
             };
             Ok(serde_json::to_value(response)?)
@@ -681,21 +1953,86 @@ pub async fn handle_tool_calls(
                 CoderError::MissingArguments("CodeWrite requires arguments".to_string())
             })?;
             let args: CodeWriteArgs = serde_json::from_value(args)?;
-            code_write(&args.path, &args.content)?;
-            let mut retry = false;
-            let output = Command::new("git")
-                .args(["diff", "--exit-code", "--staged", "--", &args.path])
-                .output()
-                .map_err(|e| CoderError::GitError(e.to_string()))?;
-            if !output.status.success() {
-                warn!("File was not written: {}", args.path);
-                retry = true;
-            }
+            let scope = Scope::new(&config.agent.scope)?;
+            let path = match code_write(root, &scope, &args.path, &args.content) {
+                Err(e @ (CoderError::PathError(_) | CoderError::OutOfScope(_))) => {
+                    return Ok(serde_json::to_value(StatusResponse::rejected(
+                        e.to_string(),
+                    ))?)
+                }
+                result => result?,
+            };
+            workspace.touch(&path);
             let response = StatusResponse {
                 status: "ok".to_string(),
                 message: Some("Code written".to_string()),
                 result: None,
-                retry,
+                retry: false,
+            };
+            Ok(serde_json::to_value(response)?)
+        }
+        Tools::CodeEdit => {
+            let args = args.ok_or_else(|| {
+                CoderError::MissingArguments("CodeEdit requires arguments".to_string())
+            })?;
+            let args: CodeEditArgs = serde_json::from_value(args)?;
+            let scope = Scope::new(&config.agent.scope)?;
+            let path = match code_edit(root, &scope, &args.path, &args.edits, args.diff.as_deref())
+            {
+                Err(
+                    e @ (CoderError::PathError(_)
+                    | CoderError::OutOfScope(_)
+                    | CoderError::EditError(_)
+                    | CoderError::InvalidArguments(_)),
+                ) => {
+                    return Ok(serde_json::to_value(StatusResponse::rejected(
+                        e.to_string(),
+                    ))?)
+                }
+                result => result?,
+            };
+            workspace.touch(&path);
+            let response = StatusResponse {
+                status: "ok".to_string(),
+                message: Some(format!("Edited {}", path.display())),
+                result: None,
+                retry: false,
+            };
+            Ok(serde_json::to_value(response)?)
+        }
+        Tools::CodeDelete | Tools::CodeMove => {
+            let args = args.ok_or_else(|| {
+                CoderError::MissingArguments(format!("{} requires arguments", tool))
+            })?;
+            let scope = Scope::new(&config.agent.scope)?;
+            let result = if let Tools::CodeDelete = tool {
+                let args: CodeDeleteArgs = serde_json::from_value(args)?;
+                code_delete(root, &scope, &args.path).await
+            } else {
+                let args: CodeMoveArgs = serde_json::from_value(args)?;
+                code_move(root, &scope, &args.from, &args.to).await
+            };
+            let change = match result {
+                Err(e @ (CoderError::PathError(_) | CoderError::OutOfScope(_))) => {
+                    return Ok(serde_json::to_value(StatusResponse::rejected(
+                        e.to_string(),
+                    ))?)
+                }
+                result => result?,
+            };
+            workspace.touch(&change.from);
+            if let Some(to) = &change.to {
+                workspace.touch(to);
+            }
+            let message = match &change.to {
+                Some(to) => format!("Moved {} to {}", change.from, to),
+                None => format!("Deleted {}", change.from),
+            };
+            let response = StatusResponse {
+                status: "ok".to_string(),
+                message: Some(message),
+                result: Some(serde_json::to_value(change)?),
+                retry: false,
             };
             Ok(serde_json::to_value(response)?)
         }
@@ -704,29 +2041,12 @@ pub async fn handle_tool_calls(
                 CoderError::MissingArguments("IssueValidate requires arguments".to_string())
             })?;
             let args: IssuePullArgs = serde_json::from_value(args)?;
-            let issue = issue_pull(
-                &config.scm.name,
-                args.issue,
-                &config.scm.owner,
-                &config.scm.repository,
-            )
-            .await?;
+            let issue = issue_pull(scm, args.issue).await?;
             issue_validate(config, issue.number, &issue.title, issue.body.clone())?;
-            #[derive(Debug, Serialize)]
-            struct SanitizedIssue {
-                number: u64,
-                title: String,
-                body: Option<String>,
-            }
-            let sanitized = SanitizedIssue {
-                number: issue.number,
-                title: issue.title,
-                body: issue.body,
-            };
             let response = StatusResponse {
                 status: "ok".to_string(),
                 message: Some("Issue validated".to_string()),
-                result: Some(serde_json::to_value(sanitized)?),
+                result: Some(serde_json::to_value(issue)?),
                 retry: false,
             };
             Ok(serde_json::to_value(response)?)
@@ -736,28 +2056,11 @@ pub async fn handle_tool_calls(
                 CoderError::MissingArguments("IssuePull requires arguments".to_string())
             })?;
             let args: IssuePullArgs = serde_json::from_value(args)?;
-            let issue = issue_pull(
-                &config.scm.name,
-                args.issue,
-                &config.scm.owner,
-                &config.scm.repository,
-            )
-            .await?;
-            #[derive(Debug, Serialize)]
-            struct SanitizedIssue {
-                number: u64,
-                title: String,
-                body: Option<String>,
-            }
-            let sanitized = SanitizedIssue {
-                number: issue.number,
-                title: issue.title,
-                body: issue.body,
-            };
+            let issue = issue_pull(scm, args.issue).await?;
             let response = StatusResponse {
                 status: "ok".to_string(),
                 message: Some("Issue pulled".to_string()),
-                result: Some(serde_json::to_value(sanitized)?),
+                result: Some(serde_json::to_value(issue)?),
                 retry: false,
             };
             Ok(serde_json::to_value(response)?)
@@ -767,48 +2070,90 @@ pub async fn handle_tool_calls(
                 CoderError::MissingArguments("PullRequest requires arguments".to_string())
             })?;
             let args: PullRequestArgs = serde_json::from_value(args)?;
-            let pr = pull_request(
-                &config.scm.name,
-                &config.scm.owner,
-                &config.scm.repository,
-                &args.branch_name,
-                args.issue,
-                &args.title,
-                &args.body,
-            )
-            .await?;
-            #[derive(Debug, Serialize)]
-            struct SanitizedPullRequest {
-                number: u64,
-                title: Option<String>,
-                body: Option<String>,
-            }
-            let sanitized = SanitizedPullRequest {
-                number: pr.number,
-                title: pr.title,
-                body: pr.body,
+            let (pr, updated) = match pull_request(workspace, scm, &config.scm, &args).await {
+                Err(e @ CoderError::InvalidArguments(_)) => {
+                    return Ok(serde_json::to_value(StatusResponse::rejected(
+                        e.to_string(),
+                    ))?)
+                }
+                result => result?,
             };
             let response = StatusResponse {
                 status: "ok".to_string(),
-                message: Some("Pull request created".to_string()),
-                result: Some(serde_json::to_value(sanitized)?),
+                message: Some(if updated {
+                    format!("Pull request #{} updated", pr.number)
+                } else {
+                    "Pull request created".to_string()
+                }),
+                result: Some(serde_json::to_value(pr)?),
+                retry: false,
+            };
+            Ok(serde_json::to_value(response)?)
+        }
+        Tools::GitCommit => {
+            let args = args.ok_or_else(|| {
+                CoderError::MissingArguments("GitCommit requires arguments".to_string())
+            })?;
+            let args: GitCommitArgs = serde_json::from_value(args)?;
+            let commit = match git_commit(
+                workspace,
+                &config.scm.commit_pattern,
+                &args.message,
+                &args.paths,
+            )
+            .await
+            {
+                Err(e @ (CoderError::PathError(_) | CoderError::InvalidArguments(_))) => {
+                    return Ok(serde_json::to_value(StatusResponse::rejected(
+                        e.to_string(),
+                    ))?)
+                }
+                result => result?,
+            };
+            let response = StatusResponse {
+                status: "ok".to_string(),
+                message: Some(format!(
+                    "Committed {} files as {}",
+                    commit.files.len(),
+                    commit.hash
+                )),
+                result: Some(serde_json::to_value(commit)?),
                 retry: false,
             };
             Ok(serde_json::to_value(response)?)
         }
         Tools::CodeLint => {
             let response =
-                execute_language_specific_command(&config.language, CommandType::Lint).await?;
+                execute_language_specific_command(root, &config.language, CommandType::Lint, None)
+                    .await?;
             Ok(serde_json::to_value(response)?)
         }
         Tools::CodeAnalyse => {
-            let response =
-                execute_language_specific_command(&config.language, CommandType::Analyse).await?;
+            let response = execute_language_specific_command(
+                root,
+                &config.language,
+                CommandType::Analyse,
+                None,
+            )
+            .await?;
             Ok(serde_json::to_value(response)?)
         }
         Tools::CodeTest => {
-            let response =
-                execute_language_specific_command(&config.language, CommandType::Test).await?;
+            let args: CodeTestArgs = match args {
+                Some(args) => serde_json::from_value(args)?,
+                None => CodeTestArgs::default(),
+            };
+            let response = match execute_language_specific_command(
+                root,
+                &config.language,
+                CommandType::Test,
+                args.filter.as_deref(),
+            )
+            .await
+            {
+                Err(e @ CoderError::InvalidArguments(_)) => StatusResponse::rejected(e.to_string()),
+                result => result?,
+            };
             Ok(serde_json::to_value(response)?)
         }
         Tools::DocsReference => {
@@ -816,8 +2161,50 @@ pub async fn handle_tool_calls(
                 CoderError::MissingArguments("DocsReference requires arguments".to_string())
             })?;
             let args: DocsReferenceArgs = serde_json::from_value(args)?;
-            docs_reference(&args.term).await?;
-            Ok(serde_json::to_value(())?)
+            // Build output such as target/doc is gitignored, a worktree never contains it
+            let snippets =
+                match docs_reference(workspace.repository(), &config.language, &args.term).await {
+                    Err(e @ CoderError::InvalidArguments(_)) => {
+                        return Ok(serde_json::to_value(StatusResponse::rejected(
+                            e.to_string(),
+                        ))?)
+                    }
+                    result => result?,
+                };
+            let message = if snippets.is_empty() {
+                format!("No documentation found for '{}'", args.term)
+            } else {
+                format!("Found {} documentation references", snippets.len())
+            };
+            let response = StatusResponse {
+                status: "ok".to_string(),
+                message: Some(message),
+                result: Some(serde_json::to_value(snippets)?),
+                retry: false,
+            };
+            Ok(serde_json::to_value(response)?)
+        }
+        Tools::GitLog | Tools::GitBlame | Tools::GitDiff => {
+            let args = args.unwrap_or_else(|| json!({}));
+            let result = match git_history(root, tool, args, &config.scm).await {
+                Err(
+                    e @ (CoderError::PathError(_)
+                    | CoderError::InvalidArguments(_)
+                    | CoderError::GitError(_)),
+                ) => {
+                    return Ok(serde_json::to_value(StatusResponse::rejected(
+                        e.to_string(),
+                    ))?)
+                }
+                result => result?,
+            };
+            let response = StatusResponse {
+                status: "ok".to_string(),
+                message: Some(format!("{} succeeded", tool)),
+                result: Some(result),
+                retry: false,
+            };
+            Ok(serde_json::to_value(response)?)
         }
         Tools::Done => {
             done()?;
@@ -835,13 +2222,13 @@ pub async fn handle_tool_calls(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use serial_test::serial;
+    use crate::scm::fake::InMemoryScm;
+    use crate::worktree::Worktree;
     use std::fs::{self, create_dir_all, File};
     use std::io::Write;
     use tempfile::tempdir;
 
     #[test]
-    #[serial]
     fn test_code_read_success() -> Result<(), Box<dyn std::error::Error>> {
         let yaml_content = r#"
 content:
@@ -857,9 +2244,7 @@ content:
         let mut file = File::create(coder_dir.join("index.yaml"))?;
         write!(file, "{}", yaml_content)?;
 
-        std::env::set_current_dir(&dir)?;
-
-        let result = code_read("src/main.rs");
+        let result = code_read_index(&path, "src/main.rs");
         assert!(result.is_ok());
         assert_eq!(result?, "fn main() {}\n");
         drop(file);
@@ -868,26 +2253,171 @@ content:
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_code_read_index_from_worktree() -> Result<(), Box<dyn std::error::Error>> {
+        let (dir, _remote, _) = published_repo(false).await?;
+        fs::write(dir.path().join(".gitignore"), ".coder\n")?;
+        fs::create_dir_all(dir.path().join(".coder"))?;
+        fs::write(
+            dir.path().join(".coder/index.yaml"),
+            "content:\n  lib.rs: |\n    fn a() {}\n",
+        )?;
+        let worktree = Worktree::create(dir.path(), "run", "HEAD").await?;
+        assert!(!worktree.path().join(".coder").exists());
+
+        let result = handle_tool_calls(
+            &Tools::CodeRead,
+            Some(json!({ "path": "lib.rs", "from_index": true })),
+            &Workspace::for_worktree(&worktree),
+            &config::Config::default(),
+            &InMemoryScm::default(),
+        )
+        .await?;
+
+        let response: StatusResponse = serde_json::from_value(result)?;
+        assert_eq!(response.status, "ok");
+        assert_eq!(response.result, Some(json!("fn a() {}\n")));
+        worktree.remove().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_docs_reference_from_worktree() -> Result<(), Box<dyn std::error::Error>> {
+        let (dir, _remote, _) = published_repo(false).await?;
+        fs::write(dir.path().join(".gitignore"), ".coder\ntarget\n")?;
+        create_dir_all(dir.path().join("target/doc"))?;
+        fs::write(
+            dir.path().join("target/doc/widget.html"),
+            "<p>Widget::spin turns the widget.</p>",
+        )?;
+        let worktree = Worktree::create(dir.path(), "run", "HEAD").await?;
+        assert!(!worktree.path().join("target").exists());
+        let mut config = config::Config::default();
+        config.language.docs_url = String::new();
+
+        let result = handle_tool_calls(
+            &Tools::DocsReference,
+            Some(json!({ "term": "Widget::spin" })),
+            &Workspace::for_worktree(&worktree),
+            &config,
+            &InMemoryScm::default(),
+        )
+        .await?;
+
+        let response: StatusResponse = serde_json::from_value(result)?;
+        let snippets = response.result.unwrap();
+        assert_eq!(snippets[0]["source"], "target/doc/widget.html");
+        assert_eq!(snippets[0]["text"], "Widget::spin turns the widget.");
+        worktree.remove().await;
+        Ok(())
+    }
+
     #[test]
     fn test_code_read_missing_index() -> Result<(), Box<dyn std::error::Error>> {
         let dir = tempdir()?;
-        std::env::set_current_dir(&dir)?;
 
-        let result = code_read("src/main.rs");
+        let result = code_read_index(dir.path(), "src/main.rs");
         assert!(result.is_err());
         dir.close()?;
         Ok(())
     }
 
+    #[test]
+    fn test_code_read_from_working_tree() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        create_dir_all(dir.path().join("src"))?;
+        fs::write(dir.path().join("src/lib.rs"), "one\ntwo\nthree\nfour\n")?;
+
+        let file = code_read(dir.path(), "src/lib.rs", None, None)?;
+        assert_eq!(file.content, "one\ntwo\nthree\nfour\n");
+        assert_eq!(
+            (file.start_line, file.end_line, file.total_lines),
+            (1, 4, 4)
+        );
+        assert!(!file.truncated);
+
+        let file = code_read(dir.path(), "./src/lib.rs", Some(2), Some(3))?;
+        assert_eq!(file.content, "two\nthree\n");
+        assert_eq!((file.start_line, file.end_line), (2, 3));
+
+        let file = code_read(dir.path(), "src/lib.rs", Some(3), Some(100))?;
+        assert_eq!(file.content, "three\nfour\n");
+
+        assert!(matches!(
+            code_read(dir.path(), "src/lib.rs", Some(5), None),
+            Err(CoderError::InvalidArguments(_))
+        ));
+        assert!(matches!(
+            code_read(dir.path(), "src/missing.rs", None, None),
+            Err(CoderError::PathError(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_code_read_respects_gitignore_and_size_cap() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        fs::write(dir.path().join(".gitignore"), "secrets.txt\n")?;
+        fs::write(dir.path().join("secrets.txt"), "token")?;
+        let line = format!("{}\n", "x".repeat(1023));
+        fs::write(dir.path().join("big.txt"), line.repeat(100))?;
+
+        let result = code_read(dir.path(), "secrets.txt", None, None);
+        assert!(result.unwrap_err().to_string().contains("ignored"));
+
+        let file = code_read(dir.path(), "big.txt", None, None)?;
+        assert!(file.truncated);
+        assert_eq!(file.end_line, MAX_READ_BYTES / 1024);
+        assert_eq!(file.content.len(), MAX_READ_BYTES);
+        assert_eq!(file.total_lines, 100);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_code_read_sees_written_changes() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        let config = config::Config::default();
+        let scm = InMemoryScm::default();
+
+        handle_tool_calls(
+            &Tools::CodeWrite,
+            Some(json!({ "path": "src/new.rs", "content": "fn new() {}\n" })),
+            &Workspace::new(dir.path()),
+            &config,
+            &scm,
+        )
+        .await?;
+        let result = handle_tool_calls(
+            &Tools::CodeRead,
+            Some(json!({ "path": "src/new.rs" })),
+            &Workspace::new(dir.path()),
+            &config,
+            &scm,
+        )
+        .await?;
+        let response: StatusResponse = serde_json::from_value(result)?;
+
+        assert_eq!(response.status, "ok");
+        assert_eq!(response.result.unwrap()["content"], "fn new() {}\n");
+        dir.close()?;
+        Ok(())
+    }
+
     #[test]
     fn test_code_write_success() -> Result<(), Box<dyn std::error::Error>> {
         let dir = tempdir()?;
         let path = dir.path().to_path_buf();
-        std::env::set_current_dir(&path)?;
 
         let src_dir = path.join("src");
         create_dir_all(&src_dir)?;
-        code_write(src_dir.join("new.rs").to_str().unwrap(), "fn new() {}")?;
+        let scope = Scope::new(&config::ScopeConfig::default())?;
+        let written = code_write(
+            &path,
+            &scope,
+            src_dir.join("new.rs").to_str().unwrap(),
+            "fn new() {}",
+        )?;
+        assert_eq!(written, Path::new("src/new.rs"));
 
         let source_file_path = src_dir.join("new.rs");
         let fs_content = fs::read_to_string(&source_file_path)?;
@@ -896,4 +2426,1056 @@ content:
         dir.close()?;
         Ok(())
     }
+
+    #[test]
+    fn test_code_write_rejects_paths_outside_repository() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let dir = tempdir()?;
+        let root = dir.path().join("repo");
+        create_dir_all(root.join(".git"))?;
+        let scope = Scope::new(&config::ScopeConfig::default())?;
+
+        for path in [
+            "../escape.txt",
+            "/tmp/escape.txt",
+            ".git/config",
+            ".coder/config.yaml",
+        ] {
+            let result = code_write(&root, &scope, path, "content");
+            assert!(
+                matches!(result, Err(CoderError::PathError(_))),
+                "{} should be rejected",
+                path
+            );
+        }
+        assert!(!dir.path().join("escape.txt").exists());
+        assert!(!root.join(".git/config").exists());
+        assert!(!root.join(".coder").exists());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_code_write_rejects_dangling_symlinks() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        let outside = tempdir()?;
+        let scope = Scope::new(&config::ScopeConfig::default())?;
+        std::os::unix::fs::symlink(outside.path().join("new.rs"), dir.path().join("link.rs"))?;
+        std::os::unix::fs::symlink(outside.path().join("missing"), dir.path().join("dir"))?;
+
+        for path in ["link.rs", "dir/new.rs"] {
+            let result = code_write(dir.path(), &scope, path, "content");
+            assert!(
+                matches!(result, Err(CoderError::PathError(_))),
+                "{} should be rejected",
+                path
+            );
+        }
+        assert!(!outside.path().join("new.rs").exists());
+        assert!(!outside.path().join("missing").exists());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_code_write_checks_scope_of_symlink_targets() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        create_dir_all(dir.path().join("src"))?;
+        create_dir_all(dir.path().join("vendor/lib/.git"))?;
+        fs::write(dir.path().join("Cargo.toml"), "[package]")?;
+        std::os::unix::fs::symlink("../Cargo.toml", dir.path().join("src/manifest"))?;
+        let scope = Scope::new(&config::ScopeConfig {
+            allow: vec!["src/**".to_string()],
+            deny: vec![],
+        })?;
+
+        assert!(matches!(
+            code_write(dir.path(), &scope, "src/manifest", "oops"),
+            Err(CoderError::OutOfScope(_))
+        ));
+        assert_eq!(
+            fs::read_to_string(dir.path().join("Cargo.toml"))?,
+            "[package]"
+        );
+        assert!(matches!(
+            code_write(
+                dir.path(),
+                &Scope::new(&config::ScopeConfig::default())?,
+                "vendor/lib/.git/config",
+                "oops"
+            ),
+            Err(CoderError::PathError(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_code_search() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        create_dir_all(dir.path().join("src"))?;
+        fs::write(
+            dir.path().join("src/lib.rs"),
+            "fn main() {\n    panic!(\"boom\");\n}\n",
+        )?;
+        let config = config::Config::default();
+        let scm = InMemoryScm::default();
+
+        let result = handle_tool_calls(
+            &Tools::CodeSearch,
+            Some(json!({ "query": "panic!(\"boom\")", "path": "src" })),
+            &Workspace::new(dir.path()),
+            &config,
+            &scm,
+        )
+        .await?;
+        let response: StatusResponse = serde_json::from_value(result)?;
+        assert_eq!(response.message, Some("Found 1 matches".to_string()));
+        let matches = &response.result.unwrap()["matches"];
+        assert_eq!(matches[0]["path"], "src/lib.rs");
+        assert_eq!(matches[0]["line"], 2);
+
+        let result = handle_tool_calls(
+            &Tools::CodeSearch,
+            Some(json!({ "query": "panic!(", "regex": true })),
+            &Workspace::new(dir.path()),
+            &config,
+            &scm,
+        )
+        .await?;
+        let response: StatusResponse = serde_json::from_value(result)?;
+        assert_eq!(response.status, "error");
+        assert!(response.retry);
+        dir.close()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_code_list() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        create_dir_all(dir.path().join("src"))?;
+        fs::write(dir.path().join("src/lib.rs"), "fn lib() {}\n")?;
+        let config = config::Config::default();
+        let scm = InMemoryScm::default();
+
+        let result = handle_tool_calls(
+            &Tools::CodeList,
+            Some(json!({ "path": "src", "glob": "*.rs" })),
+            &Workspace::new(dir.path()),
+            &config,
+            &scm,
+        )
+        .await?;
+        let response: StatusResponse = serde_json::from_value(result)?;
+        assert_eq!(response.message, Some("Listed 1 entries".to_string()));
+        let entries = &response.result.unwrap()["entries"];
+        assert_eq!(entries[0]["path"], "src/lib.rs");
+        assert_eq!(entries[0]["type"], "file");
+        assert_eq!(entries[0]["size"], 12);
+
+        let result = handle_tool_calls(
+            &Tools::CodeList,
+            Some(json!({ "path": "src/missing.rs" })),
+            &Workspace::new(dir.path()),
+            &config,
+            &scm,
+        )
+        .await?;
+        let response: StatusResponse = serde_json::from_value(result)?;
+        assert_eq!(response.status, "error");
+        assert_eq!(
+            response.message,
+            Some("Invalid path: src/missing.rs does not exist".to_string())
+        );
+        dir.close()?;
+        Ok(())
+    }
+
+    async fn git_repo() -> Result<tempfile::TempDir, Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        git::run(dir.path(), &["init", "-q"]).await?;
+        create_dir_all(dir.path().join("src/old"))?;
+        fs::write(dir.path().join("src/old/mod.rs"), "pub fn old() {}\n")?;
+        fs::write(dir.path().join("src/dead.rs"), "")?;
+        git::run(dir.path(), &["add", "."]).await?;
+        fs::write(dir.path().join("notes.txt"), "untracked")?;
+        Ok(dir)
+    }
+
+    #[tokio::test]
+    async fn test_code_delete_stages_tracked_files() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = git_repo().await?;
+        let scope = Scope::new(&config::ScopeConfig::default())?;
+
+        let change = code_delete(dir.path(), &scope, "src/dead.rs").await?;
+        assert_eq!(change.from, "src/dead.rs");
+        assert!(change.staged);
+        assert!(!dir.path().join("src/dead.rs").exists());
+        assert_eq!(
+            git::run(dir.path(), &["diff", "--cached", "--name-status"])
+                .await?
+                .trim(),
+            "A\tsrc/old/mod.rs"
+        );
+
+        let change = code_delete(dir.path(), &scope, "notes.txt").await?;
+        assert!(!change.staged);
+        assert!(!dir.path().join("notes.txt").exists());
+
+        assert!(matches!(
+            code_delete(dir.path(), &scope, "notes.txt").await,
+            Err(CoderError::PathError(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_code_move_stages_tracked_files() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = git_repo().await?;
+        let scope = Scope::new(&config::ScopeConfig::default())?;
+
+        let change = code_move(dir.path(), &scope, "src/old", "src/new/module").await?;
+        assert_eq!(change.to, Some("src/new/module".to_string()));
+        assert!(change.staged);
+        assert!(dir.path().join("src/new/module/mod.rs").exists());
+        assert!(!dir.path().join("src/old").exists());
+        assert!(git::run(dir.path(), &["diff", "--cached", "--name-only"])
+            .await?
+            .contains("src/new/module/mod.rs"));
+
+        assert!(matches!(
+            code_move(dir.path(), &scope, "src/dead.rs", "src/new/module/mod.rs").await,
+            Err(CoderError::PathError(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_code_write_after_move() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = git_repo().await?;
+        let workspace = Workspace::new(dir.path());
+        let config = config::Config::default();
+        let scm = InMemoryScm::default();
+
+        handle_tool_calls(
+            &Tools::CodeMove,
+            Some(json!({ "from": "src/old/mod.rs", "to": "src/new.rs" })),
+            &workspace,
+            &config,
+            &scm,
+        )
+        .await?;
+        let result = handle_tool_calls(
+            &Tools::CodeWrite,
+            Some(json!({ "path": "src/new.rs", "content": "pub fn new() {}\n" })),
+            &workspace,
+            &config,
+            &scm,
+        )
+        .await?;
+        let response: StatusResponse = serde_json::from_value(result)?;
+
+        assert_eq!(response.status, "ok");
+        assert!(!response.retry);
+        assert_eq!(
+            fs::read_to_string(dir.path().join("src/new.rs"))?,
+            "pub fn new() {}\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_code_delete_and_move_respect_scope() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = git_repo().await?;
+        let scope = Scope::new(&config::ScopeConfig {
+            allow: vec!["src/**".to_string()],
+            deny: vec!["src/old/**".to_string()],
+        })?;
+
+        assert!(matches!(
+            code_delete(dir.path(), &scope, "notes.txt").await,
+            Err(CoderError::OutOfScope(_))
+        ));
+        assert!(matches!(
+            code_delete(dir.path(), &scope, "src").await,
+            Err(CoderError::OutOfScope(_))
+        ));
+        assert!(matches!(
+            code_move(dir.path(), &scope, "src/dead.rs", "dead.rs").await,
+            Err(CoderError::OutOfScope(_))
+        ));
+        assert!(dir.path().join("src/dead.rs").exists());
+        assert!(dir.path().join("src/old/mod.rs").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_git_history_tools() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = git_repo().await?;
+        git::run(
+            dir.path(),
+            &[
+                "-c",
+                "user.name=Jane Doe",
+                "-c",
+                "user.email=jane@example.com",
+                "commit",
+                "-q",
+                "-m",
+                "Add old module",
+            ],
+        )
+        .await?;
+        let config = config::Config::default();
+        let scm = InMemoryScm::default();
+
+        let result = handle_tool_calls(
+            &Tools::GitLog,
+            Some(json!({ "path": "src/old" })),
+            &Workspace::new(dir.path()),
+            &config,
+            &scm,
+        )
+        .await?;
+        let response: StatusResponse = serde_json::from_value(result)?;
+        assert_eq!(response.result.unwrap()[0]["subject"], "Add old module");
+
+        let result = handle_tool_calls(
+            &Tools::GitBlame,
+            Some(json!({ "path": "src/old/mod.rs", "start_line": 1, "end_line": 1 })),
+            &Workspace::new(dir.path()),
+            &config,
+            &scm,
+        )
+        .await?;
+        let response: StatusResponse = serde_json::from_value(result)?;
+        assert_eq!(response.result.unwrap()[0]["content"], "pub fn old() {}");
+
+        let result = handle_tool_calls(
+            &Tools::GitDiff,
+            Some(json!({ "base": "does-not-exist" })),
+            &Workspace::new(dir.path()),
+            &config,
+            &scm,
+        )
+        .await?;
+        let response: StatusResponse = serde_json::from_value(result)?;
+        assert_eq!(response.status, "error");
+        assert!(response.retry);
+        dir.close()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_code_edit() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        let scope = Scope::new(&config::ScopeConfig::default())?;
+        fs::write(dir.path().join("lib.rs"), "fn a() {}\nfn b() {}\n")?;
+
+        let edits = [SearchReplace {
+            search: "fn b() {}".to_string(),
+            replace: "fn b() -> u8 { 1 }".to_string(),
+        }];
+        code_edit(dir.path(), &scope, "lib.rs", &edits, None)?;
+        assert_eq!(
+            fs::read_to_string(dir.path().join("lib.rs"))?,
+            "fn a() {}\nfn b() -> u8 { 1 }\n"
+        );
+
+        let diff = "@@ -1,2 +1,2 @@\n-fn a() {}\n+fn a() -> u8 { 0 }\n fn b() -> u8 { 1 }\n";
+        code_edit(dir.path(), &scope, "lib.rs", &[], Some(diff))?;
+        assert_eq!(
+            fs::read_to_string(dir.path().join("lib.rs"))?,
+            "fn a() -> u8 { 0 }\nfn b() -> u8 { 1 }\n"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_code_edit_failure_leaves_file_untouched() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let dir = tempdir()?;
+        fs::write(dir.path().join("lib.rs"), "one\ntwo\n")?;
+        let config = config::Config::default();
+        let scm = InMemoryScm::default();
+
+        let result = handle_tool_calls(
+            &Tools::CodeEdit,
+            Some(json!({
+                "path": "lib.rs",
+                "edits": [
+                    { "search": "one", "replace": "1" },
+                    { "search": "three", "replace": "3" }
+                ]
+            })),
+            &Workspace::new(dir.path()),
+            &config,
+            &scm,
+        )
+        .await?;
+        let response: StatusResponse = serde_json::from_value(result)?;
+
+        assert_eq!(response.status, "error");
+        assert!(response.retry);
+        assert_eq!(
+            response.message,
+            Some("Edit failed: edit 2 did not match".to_string())
+        );
+        assert_eq!(fs::read_to_string(dir.path().join("lib.rs"))?, "one\ntwo\n");
+        dir.close()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_code_write_reports_path_errors() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+
+        let config = config::Config::default();
+        let scm = InMemoryScm::default();
+
+        let result = handle_tool_calls(
+            &Tools::CodeWrite,
+            Some(json!({ "path": "../../etc/whatever", "content": "oops" })),
+            &Workspace::new(dir.path()),
+            &config,
+            &scm,
+        )
+        .await?;
+        let response: StatusResponse = serde_json::from_value(result)?;
+
+        assert_eq!(response.status, "error");
+        assert!(response.retry);
+        assert_eq!(
+            response.message,
+            Some("Invalid path: ../../etc/whatever escapes the repository".to_string())
+        );
+        dir.close()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_code_write_rejects_out_of_scope_path() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+
+        let mut config = config::Config::default();
+        config.agent.scope.allow = vec!["src/**".to_string()];
+        let scm = InMemoryScm::default();
+
+        let result = handle_tool_calls(
+            &Tools::CodeWrite,
+            Some(json!({ "path": "Cargo.toml", "content": "[package]" })),
+            &Workspace::new(dir.path()),
+            &config,
+            &scm,
+        )
+        .await?;
+        let response: StatusResponse = serde_json::from_value(result)?;
+
+        assert_eq!(response.status, "error");
+        assert!(response.retry);
+        assert!(response
+            .message
+            .unwrap()
+            .contains("Cargo.toml is outside of the change scope"));
+        assert!(!dir.path().join("Cargo.toml").exists());
+        dir.close()?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_issue_pull_uses_scm_provider() -> Result<(), Box<dyn std::error::Error>> {
+        let scm = InMemoryScm::with_issues(vec![Issue {
+            number: 3,
+            title: "Crash on start".to_string(),
+            body: Some("It crashes".to_string()),
+        }]);
+        let dir = tempdir()?;
+        let config = config::Config::default();
+
+        let result = handle_tool_calls(
+            &Tools::IssuePull,
+            Some(json!({ "issue": "3", "scm": "github" })),
+            &Workspace::new(dir.path()),
+            &config,
+            &scm,
+        )
+        .await?;
+        let response: StatusResponse = serde_json::from_value(result)?;
+
+        assert_eq!(response.message, Some("Issue pulled".to_string()));
+        let issue = response.result.unwrap();
+        assert_eq!(issue["number"], 3);
+        assert_eq!(issue["title"], "Crash on start");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_issue_validate_rejects_missing_template_sections(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        let scm = InMemoryScm::with_issues(vec![Issue {
+            number: 4,
+            title: "Incomplete".to_string(),
+            body: Some("## Description\nSomething".to_string()),
+        }]);
+        let config = config::Config::default();
+
+        let result = handle_tool_calls(
+            &Tools::IssueValidate,
+            Some(json!({ "issue": 4, "scm": "github" })),
+            &Workspace::new(dir.path()),
+            &config,
+            &scm,
+        )
+        .await;
+
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Missing required section: Steps to Reproduce"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failing_command_returns_output() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        let language = config::LanguageConfig {
+            test_command: "ls /definitely/not/here".into(),
+            ..config::Config::default().language
+        };
+
+        let response =
+            execute_language_specific_command(dir.path(), &language, CommandType::Test, None)
+                .await?;
+
+        assert_eq!(response.status, "failed");
+        assert!(!response.retry);
+        let result = response.result.unwrap();
+        assert_ne!(result["exit_code"], json!(0));
+        assert!(result["stderr"]
+            .as_str()
+            .unwrap()
+            .contains("/definitely/not/here"));
+        assert_eq!(result["truncated"], false);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_command_caps_diagnostics() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        let language = config::LanguageConfig {
+            analyse: r#"for i in $(seq 1 200); do printf 'warning: w%s\n --> src/lib.rs:%s:1\n' $i $i; done; printf 'error: e\n --> src/main.rs:1:1\n' >&2"#.into(),
+            ..config::Config::default().language
+        };
+
+        let response =
+            execute_language_specific_command(dir.path(), &language, CommandType::Analyse, None)
+                .await?;
+
+        assert_eq!(
+            response.message,
+            Some(format!(
+                "Command '{}' succeeded with 201 diagnostics",
+                language.analyse.options().command
+            ))
+        );
+        let result = response.result.unwrap();
+        let diagnostics = result["diagnostics"].as_array().unwrap();
+        assert_eq!(diagnostics.len(), MAX_COMMAND_DIAGNOSTICS);
+        assert_eq!(diagnostics[0]["file"], "src/main.rs");
+        assert_eq!(diagnostics[1]["message"], "w1");
+        assert_eq!(result["diagnostics_truncated"], true);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_command_runs_in_shell_with_options() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        fs::create_dir_all(dir.path().join("crates/core"))?;
+        fs::write(dir.path().join("crates/core/my test.txt"), "")?;
+        let language = config::LanguageConfig {
+            test_command: config::CommandConfig::Options(config::CommandOptions {
+                command: r#"ls "my test.txt" | wc -l && echo "$GREETING""#.to_string(),
+                env: [("GREETING".to_string(), "hello world".to_string())].into(),
+                cwd: Some("crates/core".to_string()),
+                timeout: Some(30),
+            }),
+            ..config::Config::default().language
+        };
+
+        let response =
+            execute_language_specific_command(dir.path(), &language, CommandType::Test, None)
+                .await?;
+
+        assert_eq!(response.status, "ok");
+        let stdout = response.result.unwrap()["stdout"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let lines: Vec<&str> = stdout.lines().map(str::trim).collect();
+        assert_eq!(lines, vec!["1", "hello world"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_command_timeout_and_cwd_outside_repository(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        let mut options = config::CommandOptions {
+            command: "sleep 5".to_string(),
+            env: Default::default(),
+            cwd: None,
+            timeout: Some(1),
+        };
+        let language = config::LanguageConfig {
+            analyse: config::CommandConfig::Options(options.clone()),
+            ..config::Config::default().language
+        };
+
+        let response =
+            execute_language_specific_command(dir.path(), &language, CommandType::Analyse, None)
+                .await?;
+        assert_eq!(response.status, "failed");
+        assert!(response
+            .message
+            .unwrap()
+            .contains("timed out after 1 seconds"));
+
+        options.cwd = Some("../".to_string());
+        let language = config::LanguageConfig {
+            analyse: config::CommandConfig::Options(options),
+            ..config::Config::default().language
+        };
+        let result =
+            execute_language_specific_command(dir.path(), &language, CommandType::Analyse, None)
+                .await;
+        assert!(matches!(result, Err(CoderError::PathError(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_code_test_with_filter() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        let language = config::LanguageConfig {
+            test_command: "echo all".into(),
+            test_filter: Some("echo only {filter}".to_string()),
+            ..config::Config::default().language
+        };
+
+        let response = execute_language_specific_command(
+            dir.path(),
+            &language,
+            CommandType::Test,
+            Some("it's $(parser) && echo injected"),
+        )
+        .await?;
+        assert_eq!(response.status, "ok");
+        assert_eq!(
+            response.result.unwrap()["stdout"],
+            "only it's $(parser) && echo injected\n"
+        );
+
+        let response =
+            execute_language_specific_command(dir.path(), &language, CommandType::Test, Some(""))
+                .await?;
+        assert_eq!(response.result.unwrap()["stdout"], "all\n");
+
+        let language = config::LanguageConfig {
+            test_filter: None,
+            ..language
+        };
+        let result = execute_language_specific_command(
+            dir.path(),
+            &language,
+            CommandType::Test,
+            Some("parser"),
+        )
+        .await;
+        assert!(matches!(result, Err(CoderError::InvalidArguments(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_command_that_cannot_start_is_an_error() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let dir = tempdir()?;
+        let language = config::LanguageConfig {
+            shell: vec!["definitely-not-a-shell".to_string()],
+            ..config::Config::default().language
+        };
+
+        let result =
+            execute_language_specific_command(dir.path(), &language, CommandType::Lint, None).await;
+
+        assert!(matches!(result, Err(CoderError::CommandError(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncate_output_keeps_head_and_tail() {
+        assert_eq!(truncate_output("short", 16), ("short".to_string(), false));
+
+        let output = format!("first line\n{}\ntest result: FAILED", "x".repeat(1000));
+        let (truncated, cut) = truncate_output(&output, 100);
+        assert!(cut);
+        assert!(truncated.starts_with("first line"));
+        assert!(truncated.ends_with("test result: FAILED"));
+        assert!(truncated.contains("bytes omitted"));
+    }
+
+    fn scm_config() -> config::ScmConfig {
+        config::ScmConfig {
+            base_branch: Some("main".to_string()),
+            ..config::Config::default().scm
+        }
+    }
+
+    fn pull_request_args(commit_message: Option<&str>) -> PullRequestArgs {
+        PullRequestArgs {
+            issue: 1,
+            title: "Fix it".to_string(),
+            body: "Fixes #1".to_string(),
+            commit_message: commit_message.map(str::to_string),
+        }
+    }
+
+    /// Repository with one commit on main, optionally pushing to a bare `origin`, and a
+    /// workspace in which the agent changed `lib.rs` and created `new.rs`
+    async fn published_repo(
+        with_remote: bool,
+    ) -> Result<(tempfile::TempDir, tempfile::TempDir, Workspace), Box<dyn std::error::Error>> {
+        let remote = tempdir()?;
+        let dir = tempdir()?;
+        git::run(remote.path(), &["init", "-q", "--bare"]).await?;
+        git::run(dir.path(), &["init", "-q", "-b", "main"]).await?;
+        git::run(dir.path(), &["config", "user.name", "Jane Doe"]).await?;
+        git::run(dir.path(), &["config", "user.email", "jane@example.com"]).await?;
+        fs::write(dir.path().join("lib.rs"), "fn a() {}\n")?;
+        git::run(dir.path(), &["add", "."]).await?;
+        git::run(dir.path(), &["commit", "-q", "-m", "Initial commit"]).await?;
+        if with_remote {
+            git::run(
+                dir.path(),
+                &["remote", "add", "origin", &remote.path().to_string_lossy()],
+            )
+            .await?;
+        }
+        fs::write(dir.path().join("lib.rs"), "fn a() { fixed() }\n")?;
+        fs::write(dir.path().join("new.rs"), "fn b() {}\n")?;
+        let workspace = Workspace::new(dir.path());
+        workspace.touch("lib.rs");
+        workspace.touch("new.rs");
+        Ok((dir, remote, workspace))
+    }
+
+    #[tokio::test]
+    async fn test_pull_request_pushes_branch() -> Result<(), Box<dyn std::error::Error>> {
+        let (dir, remote, workspace) = published_repo(true).await?;
+        // Left behind by a command, not by the agent
+        fs::write(dir.path().join("build.log"), "compiling\n")?;
+        let scm = InMemoryScm::default();
+
+        let (pr, updated) = pull_request(
+            &workspace,
+            &scm,
+            &scm_config(),
+            &pull_request_args(Some("fix(lib): call fixed from a")),
+        )
+        .await?;
+
+        assert_eq!(pr.number, 1);
+        assert!(!updated);
+        assert_eq!(scm.pull_requests.lock().unwrap()[0].0, "coder/issue-1");
+        let pushed = git::run(remote.path(), &["log", "--format=%s", "coder/issue-1"]).await?;
+        assert_eq!(pushed.lines().next(), Some("fix(lib): call fixed from a"));
+        let files = git::run(remote.path(), &["ls-tree", "--name-only", "coder/issue-1"]).await?;
+        assert_eq!(files.lines().collect::<Vec<_>>(), vec!["lib.rs", "new.rs"]);
+        assert!(workspace.touched().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pull_request_updates_open_pull_request() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let (dir, remote, workspace) = published_repo(true).await?;
+        let scm = InMemoryScm::default();
+        pull_request(
+            &workspace,
+            &scm,
+            &scm_config(),
+            &pull_request_args(Some("fix: call fixed from a")),
+        )
+        .await?;
+        let first = git::run(remote.path(), &["rev-parse", "coder/issue-1"]).await?;
+        // Like the worktree of a finished run, nothing keeps the branch checked out
+        git::run(dir.path(), &["checkout", "-q", "--detach"]).await?;
+
+        // The next run starts from the branch of the open pull request
+        let start_point = git::fetch_branch(dir.path(), "origin", "coder/issue-1").await?;
+        let next = tempdir()?;
+        let next_path = next.path().join("run");
+        git::run(
+            dir.path(),
+            &[
+                "worktree",
+                "add",
+                "-q",
+                "--detach",
+                &next_path.to_string_lossy(),
+                &start_point,
+            ],
+        )
+        .await?;
+        fs::write(next_path.join("new.rs"), "fn b() { fixed() }\n")?;
+        let workspace = Workspace::new(&next_path);
+        workspace.touch("new.rs");
+
+        let args = PullRequestArgs {
+            title: "Fix a and b".to_string(),
+            body: "Fixes #1 everywhere".to_string(),
+            ..pull_request_args(Some("fix: call fixed from b"))
+        };
+        let (pr, updated) = pull_request(&workspace, &scm, &scm_config(), &args).await?;
+
+        assert!(updated);
+        assert_eq!(pr.number, 1);
+        assert_eq!(pr.title.as_deref(), Some("Fix a and b"));
+        assert_eq!(scm.pull_requests.lock().unwrap().len(), 1);
+        let pushed = git::run(remote.path(), &["log", "--format=%s", "coder/issue-1"]).await?;
+        assert_eq!(
+            pushed.lines().collect::<Vec<_>>(),
+            vec![
+                "fix: call fixed from b",
+                "fix: call fixed from a",
+                "Initial commit"
+            ]
+        );
+
+        // A failed update puts the previous commits back
+        restore_remote_branch(
+            &next_path,
+            "origin",
+            "coder/issue-1",
+            Some(first.trim().to_string()),
+        )
+        .await?;
+        assert_eq!(
+            git::run(remote.path(), &["rev-parse", "coder/issue-1"]).await?,
+            first
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pull_request_rolls_back_failed_push() -> Result<(), Box<dyn std::error::Error>> {
+        let (dir, _remote, workspace) = published_repo(false).await?;
+        let scm = InMemoryScm::default();
+
+        let result = pull_request(
+            &workspace,
+            &scm,
+            &scm_config(),
+            &pull_request_args(Some("fix: address issue #1")),
+        )
+        .await;
+
+        let Err(CoderError::GitError(message)) = result else {
+            panic!("expected a git error, got {:?}", result);
+        };
+        assert!(message.contains("git push"));
+        assert!(message.contains("origin"));
+        assert!(scm.pull_requests.lock().unwrap().is_empty());
+
+        // Back on main with the initial commit, the branch is gone and the changes are stashed
+        assert_eq!(
+            git::run(dir.path(), &["branch", "--show-current"])
+                .await?
+                .trim(),
+            "main"
+        );
+        assert_eq!(
+            git::run(dir.path(), &["log", "--format=%s"]).await?.trim(),
+            "Initial commit"
+        );
+        assert!(git::run(dir.path(), &["branch", "--list", "coder/issue-1"])
+            .await?
+            .is_empty());
+        assert!(git::run(dir.path(), &["stash", "list"])
+            .await?
+            .contains("coder: changes for issue #1"));
+        assert!(!dir.path().join("new.rs").exists());
+
+        git::run(dir.path(), &["stash", "pop", "-q"]).await?;
+        assert_eq!(
+            fs::read_to_string(dir.path().join("lib.rs"))?,
+            "fn a() { fixed() }\n"
+        );
+        assert!(dir.path().join("new.rs").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pull_request_from_fork_targets_detected_base(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (dir, fork, workspace) = published_repo(false).await?;
+        let upstream = tempdir()?;
+        git::run(upstream.path(), &["init", "-q", "--bare", "-b", "develop"]).await?;
+        git::run(
+            dir.path(),
+            &[
+                "remote",
+                "add",
+                "upstream",
+                &upstream.path().to_string_lossy(),
+            ],
+        )
+        .await?;
+        git::run(
+            dir.path(),
+            &["push", "-q", "upstream", "HEAD:refs/heads/develop"],
+        )
+        .await?;
+        git::run(
+            dir.path(),
+            &["remote", "add", "fork", &fork.path().to_string_lossy()],
+        )
+        .await?;
+        let scm_config = config::ScmConfig {
+            remote: "upstream".to_string(),
+            fork: Some(config::ForkConfig {
+                owner: "coder-bot".to_string(),
+                remote: "fork".to_string(),
+            }),
+            ..config::Config::default().scm
+        };
+        let scm = InMemoryScm::default();
+
+        pull_request(
+            &workspace,
+            &scm,
+            &scm_config,
+            &pull_request_args(Some("fix: address issue #1")),
+        )
+        .await?;
+
+        {
+            let pull_requests = scm.pull_requests.lock().unwrap();
+            assert_eq!(pull_requests[0].0, "coder-bot:coder/issue-1");
+            assert_eq!(pull_requests[0].1, "develop");
+        }
+        assert!(
+            git::run(fork.path(), &["rev-parse", "--verify", "coder/issue-1"])
+                .await
+                .is_ok()
+        );
+        assert!(
+            git::run(upstream.path(), &["rev-parse", "--verify", "coder/issue-1"])
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pull_request_requires_valid_commit_message(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (dir, _remote, workspace) = published_repo(true).await?;
+        let scm = InMemoryScm::default();
+
+        for message in [None, Some("Fixed the bug")] {
+            let result =
+                pull_request(&workspace, &scm, &scm_config(), &pull_request_args(message)).await;
+            assert!(matches!(result, Err(CoderError::InvalidArguments(_))));
+        }
+
+        // Rejected before anything was changed
+        assert_eq!(
+            git::run(dir.path(), &["branch", "--show-current"])
+                .await?
+                .trim(),
+            "main"
+        );
+        assert!(scm.pull_requests.lock().unwrap().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_git_commit_splits_changes() -> Result<(), Box<dyn std::error::Error>> {
+        let (dir, remote, workspace) = published_repo(true).await?;
+        let config = config::Config {
+            scm: scm_config(),
+            ..config::Config::default()
+        };
+        let scm = InMemoryScm::default();
+        fs::write(dir.path().join("build.log"), "compiling\n")?;
+
+        let commit = |message: &str, paths: Value| {
+            handle_tool_calls(
+                &Tools::GitCommit,
+                Some(json!({ "message": message, "paths": paths })),
+                &workspace,
+                &config,
+                &scm,
+            )
+        };
+
+        let result = commit("Add b", json!(["new.rs"])).await?;
+        let response: StatusResponse = serde_json::from_value(result)?;
+        assert!(response.retry);
+        assert!(response.message.unwrap().contains("does not match"));
+
+        let result = commit("chore: add the build log", json!(["build.log"])).await?;
+        let response: StatusResponse = serde_json::from_value(result)?;
+        assert!(response.retry);
+        assert!(response.message.unwrap().contains("was not changed by you"));
+
+        let result = commit("feat: add b", json!(["new.rs"])).await?;
+        let response: StatusResponse = serde_json::from_value(result)?;
+        assert_eq!(response.status, "ok");
+        assert_eq!(response.result.unwrap()["files"], json!(["new.rs"]));
+        assert_eq!(workspace.touched(), vec![PathBuf::from("lib.rs")]);
+
+        // A rename staged by code_move is committed as a whole
+        handle_tool_calls(
+            &Tools::CodeMove,
+            Some(json!({ "from": "lib.rs", "to": "src/lib.rs" })),
+            &workspace,
+            &config,
+            &scm,
+        )
+        .await?;
+        let result = commit("fix(lib): call fixed from a\n\nFixes #1", json!([])).await?;
+        let response: StatusResponse = serde_json::from_value(result)?;
+        assert_eq!(response.status, "ok");
+        assert_eq!(
+            response.result.unwrap()["files"],
+            json!(["lib.rs", "src/lib.rs"])
+        );
+
+        let result = commit("fix: nothing", json!([])).await?;
+        let response: StatusResponse = serde_json::from_value(result)?;
+        assert!(response.retry);
+
+        // Everything is committed already, no commit message is needed
+        pull_request(&workspace, &scm, &scm_config(), &pull_request_args(None)).await?;
+        let pushed = git::run(remote.path(), &["log", "--format=%s", "coder/issue-1"]).await?;
+        assert_eq!(
+            pushed.lines().collect::<Vec<_>>(),
+            vec![
+                "fix(lib): call fixed from a",
+                "feat: add b",
+                "Initial commit"
+            ]
+        );
+        let files = git::run(
+            remote.path(),
+            &["ls-tree", "-r", "--name-only", "coder/issue-1"],
+        )
+        .await?;
+        assert_eq!(
+            files.lines().collect::<Vec<_>>(),
+            vec!["new.rs", "src/lib.rs"]
+        );
+        assert!(dir.path().join("build.log").exists());
+        Ok(())
+    }
 }
@@ -0,0 +1,92 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::worktree::Worktree;
+
+/// Directory the tools of a run operate in, together with the files the agent changed there
+///
+/// Only the changed files are committed, so build output or stray files the commands leave
+/// behind never end up in a pull request.
+pub struct Workspace {
+    root: PathBuf,
+    /// Developer's repository, holding the gitignored `.coder` directory a worktree lacks
+    repository: PathBuf,
+    touched: Mutex<BTreeSet<PathBuf>>,
+}
+
+impl Workspace {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            repository: root.to_path_buf(),
+            touched: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    /// Work in the worktree of a run, reading `.coder` from its repository
+    pub fn for_worktree(worktree: &Worktree) -> Self {
+        Self {
+            repository: worktree.repository().to_path_buf(),
+            ..Self::new(worktree.path())
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn repository(&self) -> &Path {
+        &self.repository
+    }
+
+    /// Record a path, relative to the root, the agent created, changed, moved or deleted
+    pub fn touch(&self, path: impl Into<PathBuf>) {
+        if let Ok(mut touched) = self.touched.lock() {
+            touched.insert(path.into());
+        }
+    }
+
+    /// Paths the agent changed since they were last committed
+    pub fn touched(&self) -> Vec<PathBuf> {
+        self.touched
+            .lock()
+            .map(|touched| touched.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Forget paths once their changes are committed
+    pub fn committed(&self, paths: &[PathBuf]) {
+        if let Ok(mut touched) = self.touched.lock() {
+            for path in paths {
+                touched.remove(path);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_touched_until_committed() {
+        let workspace = Workspace::new(Path::new("/repo"));
+        assert_eq!(workspace.root(), Path::new("/repo"));
+        assert_eq!(workspace.repository(), Path::new("/repo"));
+        assert!(workspace.touched().is_empty());
+
+        workspace.touch("src/lib.rs");
+        workspace.touch("README.md");
+        workspace.touch("src/lib.rs");
+        assert_eq!(
+            workspace.touched(),
+            vec![PathBuf::from("README.md"), PathBuf::from("src/lib.rs")]
+        );
+
+        workspace.committed(&[PathBuf::from("src/lib.rs")]);
+        assert_eq!(workspace.touched(), vec![PathBuf::from("README.md")]);
+    }
+}
@@ -0,0 +1,301 @@
+use log::{info, warn};
+use std::path::{Path, PathBuf};
+
+use crate::errors::CoderError;
+use crate::git;
+
+/// Directory, relative to the repository root, the worktrees of the runs are created in
+pub const WORKTREES_DIR: &str = ".coder/worktrees";
+
+/// Dedicated checkout for a single agent run
+///
+/// The developer's working copy is never touched and several runs can work side by side.
+/// The worktree and the local branch created in it are removed with `remove` once the run ends,
+/// unless they hold work that was not pushed.
+pub struct Worktree {
+    repository: PathBuf,
+    path: PathBuf,
+    /// Commit the worktree was created at
+    start: String,
+}
+
+impl Worktree {
+    /// Check out a commit of the repository into `.coder/worktrees/<run_id>`
+    ///
+    /// # Arguments
+    ///
+    /// * `repository` - Root of the developer's repository
+    /// * `run_id` - Unique id of the run
+    /// * `start_point` - Commit to check out, `HEAD` or the branch of an open pull request
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Worktree, CoderError>` - The worktree, uncommitted changes of the repository are
+    ///   not part of it
+    pub async fn create(
+        repository: &Path,
+        run_id: &str,
+        start_point: &str,
+    ) -> Result<Self, CoderError> {
+        let path = repository.join(WORKTREES_DIR).join(run_id);
+
+        // Forget worktrees of runs that were killed before they could clean up
+        git::run(repository, &["worktree", "prune"]).await?;
+        git::run(
+            repository,
+            &[
+                "worktree",
+                "add",
+                "-q",
+                "--detach",
+                &path.to_string_lossy(),
+                start_point,
+            ],
+        )
+        .await?;
+        info!("Created worktree {}", path.display());
+        let start = git::run(&path, &["rev-parse", "HEAD"]).await?;
+
+        Ok(Self {
+            repository: repository.to_path_buf(),
+            path,
+            start: start.trim().to_string(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn repository(&self) -> &Path {
+        &self.repository
+    }
+
+    /// Remove the worktree and its local branch, failures are only logged
+    ///
+    /// A worktree with commits that were not pushed, or with changes that were never committed,
+    /// is kept so the work of the run is not lost.
+    pub async fn remove(self) {
+        let branch = git::run(&self.path, &["branch", "--show-current"])
+            .await
+            .map(|branch| branch.trim().to_string())
+            .unwrap_or_default();
+
+        match self.unsaved_work().await {
+            Ok(None) => {}
+            Ok(Some(reason)) => {
+                warn!(
+                    "Keeping worktree {} (branch '{}'), it has {}",
+                    self.path.display(),
+                    branch,
+                    reason
+                );
+                return;
+            }
+            Err(e) => {
+                warn!(
+                    "Keeping worktree {} (branch '{}'), failed to check for unpushed work: {}",
+                    self.path.display(),
+                    branch,
+                    e
+                );
+                return;
+            }
+        }
+
+        if let Err(e) = self.try_remove(&branch).await {
+            warn!("Failed to remove worktree {}: {}", self.path.display(), e);
+        }
+    }
+
+    /// Describe the work that would be lost by removing the worktree, if there is any
+    async fn unsaved_work(&self) -> Result<Option<String>, CoderError> {
+        let unpushed = git::run(
+            &self.path,
+            &[
+                "rev-list",
+                "--count",
+                "HEAD",
+                "--not",
+                &self.start,
+                "--remotes",
+            ],
+        )
+        .await?;
+        let unpushed = unpushed.trim();
+        if unpushed != "0" {
+            return Ok(Some(format!("{} commits that were not pushed", unpushed)));
+        }
+
+        // Leftovers next to pushed commits are build output, without commits they are the work
+        let head = git::run(&self.path, &["rev-parse", "HEAD"]).await?;
+        if head.trim() == self.start
+            && !git::run(&self.path, &["status", "--porcelain"])
+                .await?
+                .trim()
+                .is_empty()
+        {
+            return Ok(Some("changes that were not committed".to_string()));
+        }
+        Ok(None)
+    }
+
+    async fn try_remove(&self, branch: &str) -> Result<(), CoderError> {
+        git::run(
+            &self.repository,
+            &[
+                "worktree",
+                "remove",
+                "--force",
+                &self.path.to_string_lossy(),
+            ],
+        )
+        .await?;
+        // The branch was pushed by pull_request, the local copy is not needed anymore
+        if !branch.is_empty() {
+            git::run(&self.repository, &["branch", "-D", branch]).await?;
+        }
+        info!("Removed worktree {}", self.path.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    async fn repository() -> Result<tempfile::TempDir, Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        git::run(dir.path(), &["init", "-q", "-b", "main"]).await?;
+        fs::write(dir.path().join(".gitignore"), ".coder\n")?;
+        fs::write(dir.path().join("lib.rs"), "fn a() {}\n")?;
+        git::run(dir.path(), &["add", "."]).await?;
+        git::run(
+            dir.path(),
+            &[
+                "-c",
+                "user.name=Jane Doe",
+                "-c",
+                "user.email=jane@example.com",
+                "commit",
+                "-q",
+                "-m",
+                "Initial commit",
+            ],
+        )
+        .await?;
+        Ok(dir)
+    }
+
+    #[tokio::test]
+    async fn test_worktree_isolates_the_working_copy() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = repository().await?;
+        fs::write(dir.path().join("lib.rs"), "fn a() { local_edit() }\n")?;
+
+        let worktree = Worktree::create(dir.path(), "run-1", "HEAD").await?;
+        let path = worktree.path().to_path_buf();
+        assert_eq!(path, dir.path().join(".coder/worktrees/run-1"));
+        assert_eq!(fs::read_to_string(path.join("lib.rs"))?, "fn a() {}\n");
+
+        git::run(&path, &["checkout", "-q", "-b", "fix-1"]).await?;
+        fs::write(path.join("lib.rs"), "fn a() { fixed() }\n")?;
+        fs::write(path.join("new.rs"), "fn b() {}\n")?;
+
+        let other = Worktree::create(dir.path(), "run-2", "HEAD").await?;
+        assert!(!other.path().join("new.rs").exists());
+        other.remove().await;
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("lib.rs"))?,
+            "fn a() { local_edit() }\n"
+        );
+        assert!(!dir.path().join("new.rs").exists());
+        assert_eq!(
+            git::run(dir.path(), &["branch", "--show-current"])
+                .await?
+                .trim(),
+            "main"
+        );
+
+        git::run(&path, &["checkout", "-q", "--", "."]).await?;
+        fs::remove_file(path.join("new.rs"))?;
+        worktree.remove().await;
+        assert!(!path.exists());
+        assert!(git::run(dir.path(), &["branch", "--list", "fix-1"])
+            .await?
+            .is_empty());
+        Ok(())
+    }
+
+    fn commit(message: &str) -> [&str; 8] {
+        [
+            "-c",
+            "user.name=Jane Doe",
+            "-c",
+            "user.email=jane@example.com",
+            "commit",
+            "-q",
+            "-am",
+            message,
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_worktree_keeps_unpushed_work() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = repository().await?;
+        let remote = tempdir()?;
+        git::run(remote.path(), &["init", "-q", "--bare"]).await?;
+        git::run(
+            dir.path(),
+            &["remote", "add", "origin", &remote.path().to_string_lossy()],
+        )
+        .await?;
+
+        let worktree = Worktree::create(dir.path(), "run-1", "HEAD").await?;
+        let path = worktree.path().to_path_buf();
+        fs::write(path.join("lib.rs"), "fn a() { wip() }\n")?;
+        worktree.remove().await;
+        assert!(path.exists(), "uncommitted changes are kept");
+
+        let worktree = Worktree::create(dir.path(), "run-2", "HEAD").await?;
+        let path = worktree.path().to_path_buf();
+        git::run(&path, &["checkout", "-q", "-b", "fix-1"]).await?;
+        fs::write(path.join("lib.rs"), "fn a() { fixed() }\n")?;
+        git::run(&path, &commit("Fix a")).await?;
+        worktree.remove().await;
+        assert!(path.exists(), "unpushed commits are kept");
+        assert!(!git::run(dir.path(), &["branch", "--list", "fix-1"])
+            .await?
+            .is_empty());
+
+        git::run(&path, &["push", "-q", "origin", "fix-1"]).await?;
+        // The worktree of run-2 that was kept
+        let worktree = Worktree {
+            repository: dir.path().to_path_buf(),
+            path: path.clone(),
+            start: git::run(dir.path(), &["rev-parse", "main"])
+                .await?
+                .trim()
+                .to_string(),
+        };
+        fs::write(path.join("build.log"), "leftover")?;
+        worktree.remove().await;
+        assert!(!path.exists(), "pushed work is removed");
+        assert!(git::run(dir.path(), &["branch", "--list", "fix-1"])
+            .await?
+            .is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_worktree_requires_a_repository() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        assert!(matches!(
+            Worktree::create(dir.path(), "run", "HEAD").await,
+            Err(CoderError::GitError(_))
+        ));
+        Ok(())
+    }
+}